@@ -1,7 +1,20 @@
-use clap::{App, Arg};
+use clap::{App, Arg, ArgMatches};
+use ethers::{
+    types::{Address, Bytes, H256},
+    utils::parse_ether,
+};
+use std::convert::TryFrom;
+use std::path::PathBuf;
+use std::str::FromStr;
 
-pub fn cli() -> Vec<String> {
-    let matches = App::new("Rust CLI evm simulator")
+use crate::simulator::error::SimulationError;
+use crate::simulator::types::{
+    BatchTransaction, ReplayOptions, SimulationBackend, SimulationParams, SimulationParamsBuilder,
+    TraceNamespace,
+};
+
+pub fn cli() -> ArgMatches<'static> {
+    App::new("Rust CLI evm simulator")
         .version("1.0")
         .author("Michael Amadi")
         .about("A CLI app for simulating EVM chain's transactions and viewing transfers and approvals that will happen without actually executing it on mainnet")
@@ -10,14 +23,14 @@ pub fn cli() -> Vec<String> {
                 .long("from")
                 .value_name("ADDRESS")
                 .help("Source address")
-                .required(true),
+                .required_unless_one(&["batch", "tx-hash"]),
         )
         .arg(
             Arg::with_name("to")
                 .long("to")
                 .value_name("ADDRESS")
                 .help("Destination address or contract address")
-                .required(true),
+                .required_unless_one(&["batch", "tx-hash"]),
         )
         .arg(
             Arg::with_name("data")
@@ -40,6 +53,15 @@ pub fn cli() -> Vec<String> {
                 .help("Block number")
                 .required(false),
         )
+        .arg(
+            Arg::with_name("tx-index")
+                .long("tx-index")
+                .value_name("INDEX")
+                .help("Simulate as if inserted at this index within the block, the transactions mined before it run first")
+                .requires("block")
+                .conflicts_with("persist")
+                .required(false),
+        )
         .arg(
             Arg::with_name("rpc")
                 .long("rpc")
@@ -54,23 +76,242 @@ pub fn cli() -> Vec<String> {
                 .help("Persist the state after simulations")
                 .required(false),
         )
-        .get_matches();
-
-    let from = matches.value_of("from").unwrap();
-    let to = matches.value_of("to").unwrap();
-    let data = matches.value_of("data").unwrap_or_else(|| "0x");
-    let value = matches.value_of("value").unwrap_or_else(|| "0");
-    let block = matches.value_of("block").unwrap_or_default();
-    let rpc = matches.value_of("rpc").unwrap_or_default();
-    let persist = matches.value_of("persist").unwrap_or_default();
-
-    vec![
-        from.to_owned(),
-        to.to_owned(),
-        data.to_owned(),
-        value.to_owned(),
-        block.to_owned(),
-        rpc.to_owned(),
-        persist.to_owned(),
-    ]
+        .arg(
+            Arg::with_name("backend")
+                .long("backend")
+                .value_name("BACKEND")
+                .possible_values(&["anvil", "revm"])
+                .help("Execution backend, either an anvil fork or an in-process revm fork (defaults to anvil)")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("trace-namespace")
+                .long("trace-namespace")
+                .value_name("NAMESPACE")
+                .possible_values(&["auto", "debug", "trace"])
+                .help("Trace the transaction on the rpc instead of mining it on a fork, with debug_traceCall, trace_call or whichever of them the rpc serves (auto)")
+                .conflicts_with_all(&["persist", "backend", "batch"])
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("abi")
+                .long("abi")
+                .value_name("ABI_FILE")
+                .help("Abi json file(s) or directories of them used to decode custom errors and events, plain abis and compiler artifacts (foundry out/, hardhat artifacts/) are supported")
+                .multiple(true)
+                .number_of_values(1)
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("burn-address")
+                .long("burn-address")
+                .value_name("ADDRESS")
+                .help("Address where tokens sent count as burned, on top of the zero and 0x...dEaD addresses")
+                .multiple(true)
+                .number_of_values(1)
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .value_name("FORMAT")
+                .possible_values(&["text", "json"])
+                .help("Output format, json prints the full report as a single json document (defaults to text)")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("batch")
+                .long("batch")
+                .value_name("FILE")
+                .help("Json array or jsonl file of transactions ({from, to, data, value}) to simulate in order on one fork")
+                .conflicts_with_all(&["from", "to", "data", "value"])
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("tx-hash")
+                .long("tx-hash")
+                .value_name("HASH")
+                .help("Replay a mined transaction, forking right before its block unless a block is given")
+                .conflicts_with_all(&["from", "to", "data", "value", "batch", "persist", "tx-index"])
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("replay-preceding")
+                .long("replay-preceding")
+                .help("Run the transactions mined before the replayed one in its block first, so state matches exactly")
+                .requires("tx-hash")
+                .conflicts_with("block")
+                .required(false),
+        )
+        .get_matches()
+}
+
+impl TryFrom<&ArgMatches<'_>> for SimulationParams {
+    type Error = SimulationError;
+
+    fn try_from(matches: &ArgMatches<'_>) -> Result<Self, Self::Error> {
+        let mut builder = fork_builder(matches)?
+            .from(parse_flag(
+                matches,
+                "from",
+                "invalid 'from' address provided",
+            )?)
+            .to(parse_flag(matches, "to", "Invalid 'to' address provided")?);
+
+        if let Some(data) = matches.value_of("data") {
+            builder = builder.data(
+                data.parse::<Bytes>()
+                    .map_err(|_| config_error("Invalid 'input data' provided"))?,
+            );
+        }
+        if let Some(value) = matches.value_of("value") {
+            // the cli takes the value in ether
+            builder = builder.value_wei(
+                parse_ether(value).map_err(|_| config_error("Invalid ether value provided"))?,
+            );
+        }
+
+        builder.build()
+    }
+}
+
+// reads the `--batch` file, every transaction runs on the fork configured by the other flags
+pub fn batch_params(matches: &ArgMatches<'_>) -> Result<Vec<SimulationParams>, SimulationError> {
+    let path = matches
+        .value_of("batch")
+        .ok_or_else(|| config_error("no batch file provided"))?;
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| config_error(&format!("could not read batch file: {}", e)))?;
+
+    // a json array, or one json transaction per line
+    let transactions: Vec<BatchTransaction> = if content.trim_start().starts_with('[') {
+        serde_json::from_str(&content)
+            .map_err(|e| config_error(&format!("invalid batch file: {}", e)))?
+    } else {
+        content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()
+            .map_err(|e| config_error(&format!("invalid batch file: {}", e)))?
+    };
+
+    transactions
+        .into_iter()
+        .map(|tx| {
+            fork_builder(matches)?
+                .from(tx.from)
+                .to(tx.to)
+                .data(tx.data)
+                .value_wei(tx.value)
+                .build()
+        })
+        .collect()
+}
+
+// reads the `--tx-hash` flag along with the flags describing the fork it is replayed on
+pub fn replay_options(matches: &ArgMatches<'_>) -> Result<(H256, ReplayOptions), SimulationError> {
+    let tx_hash = parse_flag(matches, "tx-hash", "Invalid transaction hash provided")?;
+
+    let mut options = ReplayOptions {
+        replay_preceding: matches.is_present("replay-preceding"),
+        rpc_url: matches.value_of("rpc").map(|rpc| rpc.to_owned()),
+        abi_paths: matches
+            .values_of("abi")
+            .into_iter()
+            .flatten()
+            .map(PathBuf::from)
+            .collect(),
+        ..Default::default()
+    };
+    if matches.is_present("block") {
+        options.block_number = Some(parse_flag(
+            matches,
+            "block",
+            "Block number parsed in not a valid number",
+        )?);
+    }
+    if matches.value_of("backend") == Some("revm") {
+        options.backend = SimulationBackend::Revm;
+    }
+    options.trace_namespace = trace_namespace(matches);
+    options.burn_addresses = burn_addresses(matches)?;
+
+    Ok((tx_hash, options))
+}
+
+// applies the flags describing the fork (block, rpc, persist, backend, trace namespace, abis) and the burn addresses
+fn fork_builder(matches: &ArgMatches<'_>) -> Result<SimulationParamsBuilder, SimulationError> {
+    let mut builder = SimulationParams::builder();
+
+    if let Some(block) = matches.value_of("block") {
+        let block = block.parse::<u64>().map_err(|_| {
+            config_error("Block number parsed in not a valid number. To use the current block number, don't specify a block number at all")
+        })?;
+        builder = match matches.is_present("tx-index") {
+            true => builder.position(
+                block,
+                parse_flag(matches, "tx-index", "Invalid transaction index provided")?,
+            ),
+            false => builder.block(block),
+        };
+    }
+    if let Some(rpc) = matches.value_of("rpc") {
+        builder = builder.rpc_url(rpc);
+    }
+    if let Some(persist) = matches.value_of("persist") {
+        builder = builder.persist(
+            persist
+                .parse::<bool>()
+                .map_err(|_| config_error("invalid boolean parameter for field 'persist'."))?,
+        );
+    }
+    if matches.value_of("backend") == Some("revm") {
+        builder = builder.backend(SimulationBackend::Revm);
+    }
+    builder = builder.trace_namespace(trace_namespace(matches));
+    for abi_path in matches.values_of("abi").into_iter().flatten() {
+        builder = builder.abi_path(abi_path);
+    }
+    for burn_address in burn_addresses(matches)? {
+        builder = builder.burn_address(burn_address);
+    }
+
+    Ok(builder)
+}
+
+fn trace_namespace(matches: &ArgMatches<'_>) -> TraceNamespace {
+    match matches.value_of("trace-namespace") {
+        Some("debug") => TraceNamespace::Debug,
+        Some("trace") => TraceNamespace::Trace,
+        _ => TraceNamespace::Auto,
+    }
+}
+
+fn burn_addresses(matches: &ArgMatches<'_>) -> Result<Vec<Address>, SimulationError> {
+    matches
+        .values_of("burn-address")
+        .into_iter()
+        .flatten()
+        .map(|address| {
+            address
+                .parse::<Address>()
+                .map_err(|_| config_error("Invalid burn address provided"))
+        })
+        .collect()
+}
+
+fn parse_flag<T: FromStr>(
+    matches: &ArgMatches<'_>,
+    name: &str,
+    err: &str,
+) -> Result<T, SimulationError> {
+    matches
+        .value_of(name)
+        .and_then(|v| v.parse::<T>().ok())
+        .ok_or_else(|| config_error(err))
+}
+
+fn config_error(msg: &str) -> SimulationError {
+    SimulationError::Config(msg.to_owned())
 }
@@ -3,5 +3,5 @@ pub mod simulator;
 
 pub mod prelude {
     pub use crate::{cli, simulator};
-    pub use simulator::{print_result, simulate, types};
+    pub use simulator::{decoder, error, print_result, simulate, types};
 }
@@ -1,79 +1,140 @@
-use eyre::Result;
+use std::convert::TryFrom;
 use std::process;
 
+use error::SimulationError;
 use evm_simulator::prelude::*;
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    let args: Vec<String> = cli::cli();
-    let simulation_params = types::SimulationParams::new(&args).unwrap_or_else(|e| {
-        eprintln!("{}", e);
-        process::exit(1);
-    });
-
-    println!(
-        "\n\n\x1b[1m Simulating transaction with details:
+async fn main() {
+    let matches = cli::cli();
+    let json_output = matches.value_of("output") == Some("json");
+    let decoders = decoder::DecoderRegistry::default();
+    // picking a tracing namespace traces the transaction instead of mining it on a fork
+    let create_fork = !matches.is_present("trace-namespace");
+
+    if matches.is_present("batch") {
+        let batch = cli::batch_params(&matches).unwrap_or_else(|e| exit_with(e));
+        let batch_result = simulator::simulate_batch(batch, &decoders)
+            .await
+            .unwrap_or_else(|e| exit_with(e));
+
+        if json_output {
+            print_result::print_batch_json(&batch_result).unwrap_or_else(|e| exit_with(e));
+        } else {
+            print_result::print_batch_result(batch_result);
+        }
+        return;
+    }
+
+    if matches.is_present("tx-hash") {
+        let (tx_hash, options) = cli::replay_options(&matches).unwrap_or_else(|e| exit_with(e));
+        if !json_output {
+            println!(
+                "\n\n\x1b[1m Replaying transaction:
+    \x1b[92m Hash: \x1b[0m {:?}
+    \x1b[92m Block Number: \x1b[0m {}
+    \x1b[92m Replay Preceding: \x1b[0m {}\n",
+                tx_hash,
+                options
+                    .block_number
+                    .map(|n| n.to_string())
+                    .unwrap_or_else(|| "parent of the original block".to_owned()),
+                options.replay_preceding
+            );
+        }
+
+        let sim_result = simulator::replay(tx_hash, options, create_fork, &decoders)
+            .await
+            .unwrap_or_else(|e| exit_with(e));
+        print_report(sim_result, json_output);
+        return;
+    }
+
+    let simulation_params =
+        types::SimulationParams::try_from(&matches).unwrap_or_else(|e| exit_with(e));
+
+    // keep stdout a single json document in json mode
+    if !json_output {
+        println!(
+            "\n\n\x1b[1m Simulating transaction with details:
     \x1b[92m From: \x1b[0m {:?}
     \x1b[92m To: \x1b[0m {:?}
     \x1b[92m Data: \x1b[0m {}
     \x1b[92m Value: \x1b[0m {}
     \x1b[92m Block Number: \x1b[0m {:?}\n",
-        simulation_params.from,
-        simulation_params.to,
-        simulation_params.data,
-        simulation_params.value,
-        simulation_params.block_number
-    );
+            simulation_params.from,
+            simulation_params.to,
+            simulation_params.data,
+            simulation_params.value,
+            simulation_params.block_number
+        );
+    }
 
-    let sim_result = simulate(simulation_params, true).await?;
-    let _ = print_result::print_result(sim_result);
+    let sim_result = simulate(simulation_params, create_fork, &decoders)
+        .await
+        .unwrap_or_else(|e| exit_with(e));
+    print_report(sim_result, json_output);
+}
+
+fn print_report(sim_result: types::SimulationReport, json_output: bool) {
+    if json_output {
+        print_result::print_json(&sim_result).unwrap_or_else(|e| exit_with(e));
+    } else {
+        print_result::print_result(sim_result);
+    }
+}
+
+// the library never exits on its own, this is the one place errors are turned into exit codes
+fn exit_with(err: SimulationError) -> ! {
+    eprintln!("{}", err);
 
-    Ok(())
+    let code = match err {
+        SimulationError::Config(_) => 2,
+        SimulationError::Reverted { .. } => 3,
+        SimulationError::Rpc(_) => 4,
+        SimulationError::AnvilSpawn(_) => 5,
+        SimulationError::Decode(_) => 6,
+        SimulationError::UnsupportedTrace(_) => 7,
+    };
+    process::exit(code);
 }
 
 // still working on tests
 #[cfg(test)]
 mod test {
-    use ethers::types::{Address, U256};
-    use evm_simulator::{simulator::simulate, simulator::types};
+    use ethers::types::{Address, Bytes, U256};
+    use evm_simulator::simulator::{
+        decoder::DecoderRegistry, error::SimulationError, simulate, types,
+    };
     use eyre::Result;
     use types::{Operation, SimulationParams, SimulationResults, Standard, TokenInfo};
 
     // test runs
-    fn return_erc20_test_case() -> Vec<String> {
+    fn return_erc20_test_case() -> Result<SimulationParams, SimulationError> {
         // return a uniswap swap tx data
-
-        let from = "0x448E0F9F42746F6165Dbe6E7B77149bB0F631E6E".to_owned();
-        let to = "0x2Ec705D306b51e486B1bC0D6ebEE708E0661ADd1".to_owned();
-        let data = "0x18cbafe500000000000000000000000000000000000000000000000000394425252270000000000000000000000000000000000000000000000000000035e2b98723e13d00000000000000000000000000000000000000000000000000000000000000a0000000000000000000000000448e0f9f42746f6165dbe6e7b77149bb0f631e6e0000000000000000000000000000000000000000000000000000000064a876b70000000000000000000000000000000000000000000000000000000000000002000000000000000000000000e30bbec87855c8710729e6b8384ef9783c76379c000000000000000000000000c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2".to_owned();
-        let value = "0".to_owned();
-        let block_number = "17644319".to_owned();
-        let rpc = "".to_owned();
-        let persist = "".to_owned();
-
-        vec![from, to, data, value, block_number, rpc, persist]
+        SimulationParams::builder()
+            .from("0x448E0F9F42746F6165Dbe6E7B77149bB0F631E6E".parse::<Address>().unwrap())
+            .to("0x2Ec705D306b51e486B1bC0D6ebEE708E0661ADd1".parse::<Address>().unwrap())
+            .data("0x18cbafe500000000000000000000000000000000000000000000000000394425252270000000000000000000000000000000000000000000000000000035e2b98723e13d00000000000000000000000000000000000000000000000000000000000000a0000000000000000000000000448e0f9f42746f6165dbe6e7b77149bb0f631e6e0000000000000000000000000000000000000000000000000000000064a876b70000000000000000000000000000000000000000000000000000000000000002000000000000000000000000e30bbec87855c8710729e6b8384ef9783c76379c000000000000000000000000c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2".parse::<Bytes>().unwrap())
+            .block(17644319)
+            .build()
     }
 
-    fn return_nft_test_case() -> Vec<String> {
+    fn return_nft_test_case() -> Result<SimulationParams, SimulationError> {
         // return an erc1155 and erc20 tx
-
-        let from = "0x77c5D44F392DD825A073C417EDe8C2f8bce603F6".to_owned();
-        let to = "0x00000000000000ADc04C56Bf30aC9d3c0aAF14dC".to_owned();
-        let data = "0xe7acab24000000000000000000000000000000000000000000000000000000000000008000000000000000000000000000000000000000000000000000000000000005e00000007b02230091a7ed01230072f7006a004d60a8d4e71d599b8104250f0000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000a000000000000000000000000000000000000000000000000000000000000000010000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000046000000000000000000000000000000000000000000000000000000000000004c00000000000000000000000000b818dc9d41732617dfc5bc8dff03dac632780e1000000000000000000000000000000e7ec00e7b300774b00001314b8610022b80000000000000000000000000000000000000000000000000000000000000160000000000000000000000000000000000000000000000000000000000000022000000000000000000000000000000000000000000000000000000000000000030000000000000000000000000000000000000000000000000000000064ac23690000000000000000000000000000000000000000000000000000000064d501e50000000000000000000000000000000000000000000000000000000000000000360c6ebe0000000000000000000000000000000000000000710e918d59930ae50000007b02230091a7ed01230072f7006a004d60a8d4e71d599b8104250f0000000000000000000000000000000000000000000000000000000000000000000200000000000000000000000000000000000000000000000000000000000000010000000000000000000000000000000000000000000000000000000000000001000000000000000000000000c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000d529ae9e86000000000000000000000000000000000000000000000000000000d529ae9e8600000000000000000000000000000000000000000000000000000000000000000002000000000000000000000000000000000000000000000000000000000000000300000000000000000000000076be3b62873462d2142405439777e971754e8e77000000000000000000000000000000000000000000000000000000000000282c000000000000000000000000000000000000000000000000000000000000000200000000000000000000000000000000000000000000000000000000000000020000000000000000000000000b818dc9d41732617dfc5bc8dff03dac632780e10000000000000000000000000000000000000000000000000000000000000001000000000000000000000000c02aaa39b223fe8d0a0e5c4f27ead9083c756cc200000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000005543df729c0000000000000000000000000000000000000000000000000000005543df729c0000000000000000000000000000000a26b00c1f0df003000390027140000faa719000000000000000000000000000000000000000000000000000000000000004059577c8e8707f9b8896a85d4a59a2ef30647fb061287f000079b9fe1e5063474597f9bf2b77700bba355bd813f416da1c12048c8b976a222a3fcdbc92a7887aa000000000000000000000000000000000000000000000000000000000000007e0077c5d44f392dd825a073c417ede8c2f8bce603f60000000064add71eaab1b624b2bf2ba4bc33225f4eb7638e22f73aca43287493a1f63311f6c038a5d8ca9631edb8f32f3696d78963d536359f05834d595295a3189b2c0862236f6900000000000000000000000000000000000000000000000000000000000000282c0000000000000000000000000000000000000000000000000000000000000000000000000000360c6ebe".to_owned();
-        let value = "0".to_owned();
-        let block_number = "17673303".to_owned();
-        let rpc = "".to_owned();
-        let persist = "".to_owned();
-
-        vec![from, to, data, value, block_number, rpc, persist]
+        SimulationParams::builder()
+            .from("0x77c5D44F392DD825A073C417EDe8C2f8bce603F6".parse::<Address>().unwrap())
+            .to("0x00000000000000ADc04C56Bf30aC9d3c0aAF14dC".parse::<Address>().unwrap())
+            .data("0xe7acab24000000000000000000000000000000000000000000000000000000000000008000000000000000000000000000000000000000000000000000000000000005e00000007b02230091a7ed01230072f7006a004d60a8d4e71d599b8104250f0000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000a000000000000000000000000000000000000000000000000000000000000000010000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000046000000000000000000000000000000000000000000000000000000000000004c00000000000000000000000000b818dc9d41732617dfc5bc8dff03dac632780e1000000000000000000000000000000e7ec00e7b300774b00001314b8610022b80000000000000000000000000000000000000000000000000000000000000160000000000000000000000000000000000000000000000000000000000000022000000000000000000000000000000000000000000000000000000000000000030000000000000000000000000000000000000000000000000000000064ac23690000000000000000000000000000000000000000000000000000000064d501e50000000000000000000000000000000000000000000000000000000000000000360c6ebe0000000000000000000000000000000000000000710e918d59930ae50000007b02230091a7ed01230072f7006a004d60a8d4e71d599b8104250f0000000000000000000000000000000000000000000000000000000000000000000200000000000000000000000000000000000000000000000000000000000000010000000000000000000000000000000000000000000000000000000000000001000000000000000000000000c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000d529ae9e86000000000000000000000000000000000000000000000000000000d529ae9e8600000000000000000000000000000000000000000000000000000000000000000002000000000000000000000000000000000000000000000000000000000000000300000000000000000000000076be3b62873462d2142405439777e971754e8e77000000000000000000000000000000000000000000000000000000000000282c000000000000000000000000000000000000000000000000000000000000000200000000000000000000000000000000000000000000000000000000000000020000000000000000000000000b818dc9d41732617dfc5bc8dff03dac632780e10000000000000000000000000000000000000000000000000000000000000001000000000000000000000000c02aaa39b223fe8d0a0e5c4f27ead9083c756cc200000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000005543df729c0000000000000000000000000000000000000000000000000000005543df729c0000000000000000000000000000000a26b00c1f0df003000390027140000faa719000000000000000000000000000000000000000000000000000000000000004059577c8e8707f9b8896a85d4a59a2ef30647fb061287f000079b9fe1e5063474597f9bf2b77700bba355bd813f416da1c12048c8b976a222a3fcdbc92a7887aa000000000000000000000000000000000000000000000000000000000000007e0077c5d44f392dd825a073c417ede8c2f8bce603f60000000064add71eaab1b624b2bf2ba4bc33225f4eb7638e22f73aca43287493a1f63311f6c038a5d8ca9631edb8f32f3696d78963d536359f05834d595295a3189b2c0862236f6900000000000000000000000000000000000000000000000000000000000000282c0000000000000000000000000000000000000000000000000000000000000000000000000000360c6ebe".parse::<Bytes>().unwrap())
+            .block(17673303)
+            .build()
     }
 
     #[tokio::test]
     async fn test_swap_tx_sim_should_detect_expected_logs() -> Result<(), String> {
-        let args = return_erc20_test_case();
-        let simulation_params = SimulationParams::new(&args)?;
+        let simulation_params = return_erc20_test_case().map_err(|e| e.to_string())?;
 
-        let sim_result = simulate(simulation_params, true).await;
+        let sim_result = simulate(simulation_params, true, &DecoderRegistry::default()).await;
         let sim_result = match sim_result {
             Ok(r) => r,
             Err(_) => return Err("Simulation failed".to_owned()),
@@ -98,6 +159,8 @@ mod test {
                     .unwrap(),
                 id: None,
                 amount: U256::from_dec_str("16119000000000000").unwrap(),
+                batch_log_index: None,
+                underlying: None,
             },
             SimulationResults {
                 operation: Operation::Approval,
@@ -118,6 +181,8 @@ mod test {
                     .unwrap(),
                 id: None,
                 amount: U256::from(0),
+                batch_log_index: None,
+                underlying: None,
             },
             SimulationResults {
                 operation: Operation::Transfer,
@@ -138,20 +203,21 @@ mod test {
                     .unwrap(),
                 id: None,
                 amount: U256::from_dec_str("20210640756165174").unwrap(),
+                batch_log_index: None,
+                underlying: None,
             },
         ];
 
-        assert_eq!(sim_result, expected_result);
+        assert_eq!(sim_result.results, expected_result);
 
         Ok(())
     }
 
     #[tokio::test]
     async fn test_nft_tx_sim_should_detect_expected_logs() -> Result<(), String> {
-        let args = return_nft_test_case();
-        let simulation_params = SimulationParams::new(&args)?;
+        let simulation_params = return_nft_test_case().map_err(|e| e.to_string())?;
 
-        let sim_result = simulate(simulation_params, true).await;
+        let sim_result = simulate(simulation_params, true, &DecoderRegistry::default()).await;
         let sim_result = match sim_result {
             Ok(r) => r,
             Err(_) => return Err("Simulation failed".to_owned()),
@@ -176,6 +242,8 @@ mod test {
                     .unwrap(),
                 id: None,
                 amount: U256::from_dec_str("60000000000000000").unwrap(),
+                batch_log_index: None,
+                underlying: None,
             },
             SimulationResults {
                 operation: Operation::TransferSingle,
@@ -196,6 +264,8 @@ mod test {
                     .unwrap(),
                 id: Some(U256::from_dec_str("10284").unwrap()),
                 amount: U256::from_dec_str("2").unwrap(),
+                batch_log_index: None,
+                underlying: None,
             },
             SimulationResults {
                 operation: Operation::Transfer,
@@ -216,10 +286,12 @@ mod test {
                     .unwrap(),
                 id: None,
                 amount: U256::from_dec_str("1500000000000000").unwrap(),
+                batch_log_index: None,
+                underlying: None,
             },
         ];
 
-        assert_eq!(sim_result, expected_result);
+        assert_eq!(sim_result.results, expected_result);
 
         Ok(())
     }
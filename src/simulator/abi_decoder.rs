@@ -0,0 +1,151 @@
+use async_trait::async_trait;
+use ethers::{
+    abi::{self, Abi, Event},
+    types::H256,
+};
+use std::collections::HashMap;
+
+use super::decoder::{DecodeContext, DecodedEvent, EventDecoder};
+use super::error::Result;
+use super::types::{CustomEvent, EventField, RawLog};
+use super::utils::format_token;
+
+// Decodes any event found in the supplied abis into a custom event with named, typed fields
+pub struct AbiDecoder {
+    // events sharing a signature can still differ in which params are indexed (e.g. ERC20 and ERC721 `Transfer`)
+    events: HashMap<H256, Vec<Event>>,
+}
+
+impl AbiDecoder {
+    pub fn new(abis: &[Abi]) -> Self {
+        let mut events: HashMap<H256, Vec<Event>> = HashMap::new();
+
+        // anonymous events have no signature topic to be found by
+        for event in abis
+            .iter()
+            .flat_map(|abi| abi.events())
+            .filter(|event| !event.anonymous)
+        {
+            let same_signature = events.entry(event.signature()).or_default();
+            if !same_signature.contains(event) {
+                same_signature.push(event.clone());
+            }
+        }
+
+        AbiDecoder { events }
+    }
+
+    fn decode_log(&self, log: &RawLog) -> Option<CustomEvent> {
+        let candidates = self.events.get(log.topics.first()?)?;
+
+        candidates.iter().find_map(|event| {
+            let parsed = event
+                .parse_log(abi::RawLog {
+                    topics: log.topics.clone(),
+                    data: log.data.to_vec(),
+                })
+                .ok()?;
+
+            let fields = event
+                .inputs
+                .iter()
+                .zip(parsed.params)
+                .map(|(input, param)| EventField {
+                    name: param.name,
+                    kind: input.kind.to_string(),
+                    value: format_token(&param.value),
+                })
+                .collect();
+
+            Some(CustomEvent {
+                log_index: log.log_index,
+                address: log.address,
+                name: event.name.clone(),
+                fields,
+            })
+        })
+    }
+}
+
+#[async_trait]
+impl EventDecoder for AbiDecoder {
+    fn topics(&self) -> Vec<H256> {
+        self.events.keys().copied().collect()
+    }
+
+    async fn decode(
+        &self,
+        log: &RawLog,
+        _context: &mut DecodeContext<'_>,
+    ) -> Result<Vec<DecodedEvent>> {
+        Ok(self
+            .decode_log(log)
+            .map(DecodedEvent::Custom)
+            .into_iter()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ethers::{
+        abi::{encode, Token},
+        types::{Address, U256},
+    };
+
+    #[test]
+    fn decodes_events_by_signature_and_indexed_params() {
+        let artifact: serde_json::Value =
+            serde_json::from_str(include_str!("fixtures/out/Vault.sol/Vault.json")).unwrap();
+        let abi: Abi = serde_json::from_value(artifact["abi"].clone()).unwrap();
+        let decoder = AbiDecoder::new(&[abi.clone(), abi]);
+        let deposit = H256(ethers::utils::keccak256(
+            "Deposited(address,uint256,bytes32)",
+        ));
+        let (vault, user) = (Address::repeat_byte(0xaa), Address::repeat_byte(0x11));
+
+        // the same abi twice doesn't register its events twice
+        assert_eq!(decoder.events[&deposit].len(), 1);
+
+        let log = RawLog {
+            log_index: 3,
+            address: vault,
+            topics: vec![deposit, H256::from(user)],
+            data: encode(&[
+                Token::Uint(U256::from(1000)),
+                Token::FixedBytes(vec![0xab; 32]),
+            ])
+            .into(),
+        };
+        let event = decoder.decode_log(&log).unwrap();
+
+        assert_eq!(event.name, "Deposited");
+        assert_eq!((event.log_index, event.address), (3, vault));
+        let fields: Vec<(&str, &str, &str)> = event
+            .fields
+            .iter()
+            .map(|f| (f.name.as_str(), f.kind.as_str(), f.value.as_str()))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("user", "address", format!("{:?}", user).as_str()),
+                ("amount", "uint256", "1000"),
+                ("ref", "bytes32", format!("0x{}", "ab".repeat(32)).as_str()),
+            ]
+        );
+
+        // an unknown signature, and a known one whose topics don't match its indexed params
+        let unknown = RawLog {
+            topics: vec![H256::repeat_byte(1)],
+            ..log.clone()
+        };
+        let misshapen = RawLog {
+            topics: vec![deposit],
+            ..log
+        };
+        assert!(decoder.decode_log(&unknown).is_none());
+        assert!(decoder.decode_log(&misshapen).is_none());
+    }
+}
@@ -0,0 +1,80 @@
+use ethers::abi::Abi;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+use super::error::{Result, SimulationError};
+
+// Loads user supplied abi files. Each file can either be a plain abi array or a compiler artifact (foundry,
+// hardhat) that holds the abi under an `abi` key. A directory (e.g. foundry's `out/` or hardhat's `artifacts/`) is
+// searched for json files, those that aren't abis (build info, debug files) are skipped.
+pub fn load_abis(paths: &[PathBuf]) -> Result<Vec<Abi>> {
+    let mut abis: Vec<Abi> = Vec::new();
+
+    for path in paths.iter() {
+        if path.is_dir() {
+            for file in json_files(path)? {
+                if let Ok(abi) = load_abi(&file) {
+                    abis.push(abi);
+                }
+            }
+        } else {
+            abis.push(load_abi(path)?);
+        }
+    }
+
+    Ok(abis)
+}
+
+fn load_abi(path: &Path) -> Result<Abi> {
+    let content = std::fs::read_to_string(path).map_err(|e| {
+        SimulationError::Config(format!("could not read abi file {:?}: {}", path, e))
+    })?;
+    let json: Value = serde_json::from_str(&content).map_err(|e| {
+        SimulationError::Config(format!("abi file {:?} is not valid json: {}", path, e))
+    })?;
+
+    let abi = match json {
+        Value::Object(mut artifact) => artifact.remove("abi").ok_or_else(|| {
+            SimulationError::Config(format!("abi file {:?} has no `abi` key", path))
+        })?,
+        json => json,
+    };
+    serde_json::from_value(abi).map_err(|e| {
+        SimulationError::Config(format!("abi file {:?} is not a valid abi: {}", path, e))
+    })
+}
+
+// every json file below `dir`, sorted so the abis load in the same order every time
+fn json_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let read_error =
+        |e: std::io::Error| SimulationError::Config(format!("could not read {:?}: {}", dir, e));
+
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir).map_err(read_error)? {
+        let path = entry.map_err(read_error)?.path();
+        if path.is_dir() {
+            files.extend(json_files(&path)?);
+        } else if path.extension().is_some_and(|ext| ext == "json") {
+            files.push(path);
+        }
+    }
+    files.sort();
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn loads_the_abis_of_a_directory_and_skips_other_json() {
+        // laid out like foundry's `out/`, with the build info next to the artifacts
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/simulator/fixtures/out");
+
+        let abis = load_abis(&[dir]).unwrap();
+
+        assert_eq!(abis.len(), 1);
+        assert!(abis[0].event("Deposited").is_ok());
+    }
+}
@@ -0,0 +1,303 @@
+use ethers::types::{Address, I256, U256};
+
+use super::process_logs::native_token;
+use super::types::{
+    AssetChanges, NftAmount, Operation, SimulationBackend, SimulationParams, SimulationReport,
+    Standard, TokenChange, TokenInfo,
+};
+use super::utils::u256_to_i256;
+
+// Nets the token movements of the report into what every address gains and loses, the sender first. Native ETH comes
+// from the traced native transfers, or from the transaction's own value when the simulation didn't trace them, and
+// the sender pays for its gas whenever the gas price is known.
+pub fn asset_changes(report: &SimulationReport, params: &SimulationParams) -> Vec<AssetChanges> {
+    let mut ledger = Ledger::default();
+
+    for result in report.results.iter() {
+        match result.operation {
+            Operation::Transfer
+            | Operation::TransferSingle
+            | Operation::TransferBatch
+            | Operation::Mint
+            | Operation::Burn => ledger.transfer(
+                &result.token_info,
+                result.from,
+                result.to,
+                result.id,
+                result.amount,
+            ),
+            // WETH logs no Transfer when minting and burning its tokens
+            Operation::Wrap => ledger.change(
+                result.to,
+                &result.token_info,
+                None,
+                u256_to_i256(result.amount),
+            ),
+            Operation::Unwrap => ledger.change(
+                result.from,
+                &result.token_info,
+                None,
+                -u256_to_i256(result.amount),
+            ),
+            // approvals move nothing, vault shares and assets move through their own Transfer events
+            Operation::Approval
+            | Operation::ApprovalForAll
+            | Operation::Deposit
+            | Operation::Withdraw => {}
+        }
+    }
+
+    let traced_native = report
+        .results
+        .iter()
+        .any(|result| result.token_info.standard == Standard::Native);
+    if report.success && !traced_native && !params.value.is_zero() {
+        ledger.transfer(&native_token(), params.from, params.to, None, params.value);
+    }
+
+    // the revm backend never charges for gas
+    let gas_price = report.effective_gas_price.or(match params.backend {
+        SimulationBackend::Revm => None,
+        _ => params.gas_price,
+    });
+    if let Some(gas_price) = gas_price {
+        let gas_cost = report.gas_used.saturating_mul(gas_price);
+        ledger.change(params.from, &native_token(), None, -u256_to_i256(gas_cost));
+    }
+
+    ledger.into_changes(params.from)
+}
+
+// Running balances, addresses and their tokens kept in the order they first show up
+#[derive(Default)]
+struct Ledger {
+    accounts: Vec<(Address, Vec<Balance>)>,
+}
+
+struct Balance {
+    token_info: TokenInfo,
+    delta: I256,
+    // net amount of every nft id
+    ids: Vec<(U256, I256)>,
+}
+
+impl Ledger {
+    fn transfer(
+        &mut self,
+        token_info: &TokenInfo,
+        from: Address,
+        to: Address,
+        id: Option<U256>,
+        amount: U256,
+    ) {
+        self.change(from, token_info, id, -u256_to_i256(amount));
+        self.change(to, token_info, id, u256_to_i256(amount));
+    }
+
+    fn change(&mut self, address: Address, token_info: &TokenInfo, id: Option<U256>, amount: I256) {
+        // minted from and burned to the zero address, which doesn't hold anything
+        if address.is_zero() {
+            return;
+        }
+
+        let balances = match self.accounts.iter().position(|(a, _)| *a == address) {
+            Some(index) => &mut self.accounts[index].1,
+            None => {
+                self.accounts.push((address, Vec::new()));
+                &mut self.accounts.last_mut().unwrap().1
+            }
+        };
+        let balance = match balances.iter().position(|b| {
+            b.token_info.address == token_info.address
+                && b.token_info.standard == token_info.standard
+        }) {
+            Some(index) => &mut balances[index],
+            None => {
+                balances.push(Balance {
+                    token_info: token_info.clone(),
+                    delta: I256::zero(),
+                    ids: Vec::new(),
+                });
+                balances.last_mut().unwrap()
+            }
+        };
+
+        balance.delta = balance.delta.saturating_add(amount);
+        if let Some(id) = id {
+            match balance.ids.iter_mut().find(|(i, _)| *i == id) {
+                Some((_, net)) => *net = net.saturating_add(amount),
+                None => balance.ids.push((id, amount)),
+            }
+        }
+    }
+
+    // what didn't net out to nothing, the sender moved to the front
+    fn into_changes(self, sender: Address) -> Vec<AssetChanges> {
+        let mut changes: Vec<AssetChanges> = self
+            .accounts
+            .into_iter()
+            .map(|(address, balances)| AssetChanges {
+                address,
+                sender: address == sender,
+                tokens: balances.into_iter().filter_map(token_change).collect(),
+            })
+            .filter(|changes| !changes.tokens.is_empty())
+            .collect();
+
+        if let Some(index) = changes.iter().position(|changes| changes.sender) {
+            let sender_changes = changes.remove(index);
+            changes.insert(0, sender_changes);
+        }
+        changes
+    }
+}
+
+fn token_change(balance: Balance) -> Option<TokenChange> {
+    let nft_amounts = |gained: bool| -> Vec<NftAmount> {
+        balance
+            .ids
+            .iter()
+            .filter(|(_, net)| !net.is_zero() && net.is_positive() == gained)
+            .map(|(id, net)| NftAmount {
+                id: *id,
+                amount: net.unsigned_abs(),
+            })
+            .collect()
+    };
+    let (gained, lost) = (nft_amounts(true), nft_amounts(false));
+    if balance.delta.is_zero() && gained.is_empty() && lost.is_empty() {
+        return None;
+    }
+
+    Some(TokenChange {
+        token_info: balance.token_info,
+        delta: balance.delta,
+        gained,
+        lost,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::simulator::test_utils::{result, token};
+    use ethers::types::Bytes;
+
+    #[test]
+    fn nets_every_movement_per_address_and_token() {
+        let (buyer, seller, fee_recipient, market) = (
+            Address::repeat_byte(0x11),
+            Address::repeat_byte(0x22),
+            Address::repeat_byte(0x33),
+            Address::repeat_byte(0x44),
+        );
+        let (weth, collection, badge) = (
+            token(Standard::Eip20, 0xc0),
+            token(Standard::Eip1155, 0xc1),
+            token(Standard::Eip721, 0xc2),
+        );
+
+        let report = SimulationReport {
+            success: true,
+            gas_used: U256::from(100),
+            effective_gas_price: Some(U256::from(2)),
+            results: vec![
+                result(Operation::Transfer, &weth, buyer, seller, None, 600),
+                result(
+                    Operation::TransferSingle,
+                    &collection,
+                    seller,
+                    buyer,
+                    Some(7),
+                    2,
+                ),
+                result(Operation::Transfer, &weth, seller, fee_recipient, None, 15),
+                result(Operation::Mint, &badge, Address::zero(), buyer, Some(1), 1),
+                result(Operation::Wrap, &weth, seller, seller, None, 5),
+                // moves nothing
+                result(Operation::Approval, &weth, seller, market, None, 1000),
+                // back and forth, nets out
+                result(
+                    Operation::TransferSingle,
+                    &collection,
+                    seller,
+                    market,
+                    Some(8),
+                    1,
+                ),
+                result(
+                    Operation::TransferSingle,
+                    &collection,
+                    market,
+                    seller,
+                    Some(8),
+                    1,
+                ),
+            ],
+            ..SimulationReport::reverted(Bytes::new(), None)
+        };
+        let params = SimulationParams::builder()
+            .from(seller)
+            .to(market)
+            .value_wei(U256::from(50))
+            .build()
+            .unwrap();
+
+        let changes = asset_changes(&report, &params);
+
+        // (token, delta, ids gained, ids lost)
+        type TokenSummary = (Address, I256, usize, usize);
+        let summary: Vec<(Address, bool, Vec<TokenSummary>)> = changes
+            .iter()
+            .map(|changes| {
+                (
+                    changes.address,
+                    changes.sender,
+                    changes
+                        .tokens
+                        .iter()
+                        .map(|t| (t.token_info.address, t.delta, t.gained.len(), t.lost.len()))
+                        .collect(),
+                )
+            })
+            .collect();
+        let eth = Address::zero();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    seller,
+                    true,
+                    vec![
+                        (weth.address, I256::from(590), 0, 0),
+                        (collection.address, I256::from(-2), 0, 1),
+                        // the value sent and the gas paid
+                        (eth, I256::from(-250), 0, 0),
+                    ]
+                ),
+                (
+                    buyer,
+                    false,
+                    vec![
+                        (weth.address, I256::from(-600), 0, 0),
+                        (collection.address, I256::from(2), 1, 0),
+                        (badge.address, I256::from(1), 1, 0),
+                    ]
+                ),
+                (
+                    fee_recipient,
+                    false,
+                    vec![(weth.address, I256::from(15), 0, 0)]
+                ),
+                (market, false, vec![(eth, I256::from(50), 0, 0)]),
+            ]
+        );
+        assert_eq!(
+            changes[0].tokens[1].lost,
+            vec![NftAmount {
+                id: U256::from(7),
+                amount: U256::from(2)
+            }]
+        );
+    }
+}
@@ -0,0 +1,68 @@
+use ethers::types::{Address, U256};
+
+use super::types::{CallFrame, CallKind, FrameLog, RawLog};
+
+pub struct NativeTransfer {
+    // number of logs emitted before the transfer, to order it among the watched events
+    pub logs_before: usize,
+    pub from: Address,
+    pub to: Address,
+    pub amount: U256,
+}
+
+impl CallKind {
+    // DELEGATECALL and STATICCALL can't carry value and CALLCODE's value never leaves the calling contract
+    fn moves_value(&self) -> bool {
+        matches!(
+            self,
+            CallKind::Call | CallKind::Create | CallKind::Create2 | CallKind::SelfDestruct
+        )
+    }
+}
+
+impl CallFrame {
+    // Logs and native transfers of every frame that didn't revert, in execution order. A reverted frame takes
+    // everything that happened below it along.
+    pub(crate) fn flatten(&self) -> (Vec<RawLog>, Vec<NativeTransfer>) {
+        let mut logs: Vec<RawLog> = Vec::new();
+        let mut transfers: Vec<NativeTransfer> = Vec::new();
+        self.collect(&mut logs, &mut transfers);
+
+        (logs, transfers)
+    }
+
+    fn collect(&self, logs: &mut Vec<RawLog>, transfers: &mut Vec<NativeTransfer>) {
+        if self.reverted {
+            return;
+        }
+
+        if self.kind.moves_value() && !self.value.is_zero() {
+            transfers.push(NativeTransfer {
+                logs_before: logs.len(),
+                from: self.from,
+                to: self.to,
+                amount: self.value,
+            });
+        }
+
+        let mut frame_logs = self.logs.iter().peekable();
+        for (position, call) in self.calls.iter().enumerate() {
+            while let Some(log) = frame_logs.next_if(|log| log.position <= position) {
+                push_log(logs, log);
+            }
+            call.collect(logs, transfers);
+        }
+        for log in frame_logs {
+            push_log(logs, log);
+        }
+    }
+}
+
+fn push_log(logs: &mut Vec<RawLog>, log: &FrameLog) {
+    logs.push(RawLog {
+        log_index: logs.len(),
+        address: log.address,
+        topics: log.topics.clone(),
+        data: log.data.clone(),
+    });
+}
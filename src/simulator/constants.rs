@@ -19,45 +19,112 @@ pub const TRANSFER_BATCH: [u8; 32] = [
     74, 57, 220, 6, 212, 192, 219, 198, 75, 112, 175, 144, 253, 105, 138, 35, 58, 81, 138, 165,
     208, 126, 89, 93, 152, 59, 140, 5, 38, 200, 247, 251,
 ]; // 0x4a39dc06d4c0dbc64b70af90fd698a233a518aa5d07e595d983b8c0526c8f7fb
+pub const WETH_DEPOSIT: [u8; 32] = [
+    225, 255, 252, 196, 146, 61, 4, 181, 89, 244, 210, 154, 139, 252, 108, 218, 4, 235, 91, 13, 60,
+    70, 7, 81, 194, 64, 44, 92, 92, 201, 16, 156,
+]; // 0xe1fffcc4923d04b559f4d29a8bfc6cda04eb5b0d3c460751c2402c5c5cc9109c, Deposit(address,uint256)
+pub const WETH_WITHDRAWAL: [u8; 32] = [
+    127, 207, 83, 44, 21, 240, 166, 219, 11, 214, 208, 224, 56, 190, 167, 29, 48, 216, 8, 199, 217,
+    140, 179, 191, 114, 104, 169, 91, 245, 8, 27, 101,
+]; // 0x7fcf532c15f0a6db0bd6d0e038bea71d30d808c7d98cb3bf7268a95bf5081b65, Withdrawal(address,uint256)
+pub const VAULT_DEPOSIT: [u8; 32] = [
+    220, 188, 28, 5, 36, 15, 49, 255, 58, 208, 103, 239, 30, 227, 92, 228, 153, 119, 98, 117, 46,
+    58, 9, 82, 132, 117, 69, 68, 244, 199, 9, 215,
+]; // 0xdcbc1c05240f31ff3ad067ef1ee35ce4997762752e3a095284754544f4c709d7, Deposit(address,address,uint256,uint256)
+pub const VAULT_WITHDRAW: [u8; 32] = [
+    251, 222, 121, 125, 32, 28, 104, 27, 145, 5, 101, 41, 17, 158, 11, 2, 64, 124, 123, 185, 106,
+    74, 44, 117, 192, 31, 201, 102, 114, 50, 200, 219,
+]; // 0xfbde797d201c681b91056529119e0b02407c7bb96a4a2c75c01fc9667232c8db, Withdraw(address,address,address,uint256,uint256)
 
-// array of checked topics
-pub const CHECKED_TOPICS: [[u8; 32]; 5] = [
-    APPROVAL,
-    TRANSFER,
-    APPROVAL_FOR_ALL,
-    TRANSFER_SINGLE,
-    TRANSFER_BATCH,
-];
+// SWAP EVENTS
+pub const UNISWAP_V2_SWAP: [u8; 32] = [
+    215, 138, 217, 95, 164, 108, 153, 75, 101, 81, 208, 218, 133, 252, 39, 95, 230, 19, 206, 55,
+    101, 127, 184, 213, 227, 209, 48, 132, 1, 89, 216, 34,
+]; // 0xd78ad95fa46c994b6551d0da85fc275fe613ce37657fb8d5e3d130840159d822, Swap(address,uint256,uint256,uint256,uint256,address)
+pub const UNISWAP_V3_SWAP: [u8; 32] = [
+    196, 32, 121, 249, 74, 99, 80, 215, 230, 35, 95, 41, 23, 73, 36, 249, 40, 204, 42, 200, 24,
+    235, 100, 254, 216, 0, 78, 17, 95, 188, 202, 103,
+]; // 0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67, Swap(address,address,int256,int256,uint160,uint128,int24)
+pub const CURVE_TOKEN_EXCHANGE: [u8; 32] = [
+    139, 62, 150, 242, 184, 137, 250, 119, 28, 83, 201, 129, 180, 13, 175, 0, 95, 99, 246, 55, 241,
+    134, 159, 112, 112, 82, 209, 90, 61, 217, 113, 64,
+]; // 0x8b3e96f2b889fa771c53c981b40daf005f63f637f1869f707052d15a3dd97140, TokenExchange(address,int128,uint256,int128,uint256)
+pub const CURVE_CRYPTO_TOKEN_EXCHANGE: [u8; 32] = [
+    178, 231, 106, 233, 151, 97, 220, 19, 110, 89, 141, 74, 98, 155, 179, 71, 236, 203, 149, 50,
+    165, 248, 187, 215, 46, 24, 70, 124, 60, 52, 204, 152,
+]; // 0xb2e76ae99761dc136e598d4a629bb347eccb9532a5f8bbd72e18467c3c34cc98, TokenExchange(address,uint256,uint256,uint256,uint256)
+pub const BALANCER_SWAP: [u8; 32] = [
+    33, 112, 199, 65, 196, 21, 49, 174, 194, 14, 124, 16, 124, 36, 238, 207, 221, 21, 230, 156,
+    155, 176, 168, 221, 55, 177, 132, 11, 158, 11, 32, 123,
+]; // 0x2170c741c41531aec20e7c107c24eecfdd15e69c9bb0a8dd37b1840b9e0b207b, Swap(bytes32,address,address,uint256,uint256)
 
-// PRECOMPILES
-const EC_RECOVER: [u8; 32] = [
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-];
-const SHA2_256: [u8; 32] = [
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2,
-];
-const RIPEMD_160: [u8; 32] = [
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 3,
-];
-const IDENTITY: [u8; 32] = [
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 4,
-];
-const MOD_EXP: [u8; 32] = [
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5,
-];
-const EC_ADD: [u8; 32] = [
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 6,
-];
-const EC_MUL: [u8; 32] = [
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 7,
-];
-const EC_PAIRING: [u8; 32] = [
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 8,
-];
-const BLAKE_2F: [u8; 32] = [
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 9,
-];
+// MARKETPLACE EVENTS
+pub const SEAPORT_ORDER_FULFILLED: [u8; 32] = [
+    157, 154, 248, 227, 141, 102, 198, 46, 44, 18, 240, 34, 82, 73, 253, 157, 114, 28, 84, 184, 63,
+    72, 217, 53, 44, 151, 198, 202, 205, 203, 111, 49,
+]; // 0x9d9af8e38d66c62e2c12f0225249fd9d721c54b83f48d9352c97c6cacdcb6f31, OrderFulfilled(bytes32,address,address,address,(uint8,address,uint256,uint256)[],(uint8,address,uint256,uint256,address)[])
 
-pub const PRECOMPILES: [[u8; 32]; 9] = [
-    EC_RECOVER, SHA2_256, RIPEMD_160, IDENTITY, MOD_EXP, EC_ADD, EC_MUL, EC_PAIRING, BLAKE_2F,
+// where tokens are commonly sent to take them out of circulation, when a token has no burn function
+pub const DEAD_ADDRESS: [u8; 20] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 222, 173,
+]; // 0x000000000000000000000000000000000000dEaD
+
+// WETH9 style wrappers of each chain's native currency, by chain id. Only these are known to log Deposit and
+// Withdrawal instead of, rather than along with, a Transfer from or to the zero address.
+pub const WRAPPED_NATIVE: [(u64, [u8; 20]); 8] = [
+    (
+        1,
+        [
+            192, 42, 170, 57, 178, 35, 254, 141, 10, 14, 92, 79, 39, 234, 217, 8, 60, 117, 108, 194,
+        ],
+    ), // 0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2, WETH on Ethereum
+    (
+        10,
+        [66, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 6],
+    ), // 0x4200000000000000000000000000000000000006, WETH on Optimism
+    (
+        56,
+        [
+            187, 76, 219, 156, 189, 54, 176, 27, 209, 203, 174, 191, 45, 224, 141, 145, 115, 188,
+            9, 92,
+        ],
+    ), // 0xbb4CdB9CBd36B01bD1cBaEBF2De08d9173bc095c, WBNB on BNB Chain
+    (
+        100,
+        [
+            233, 29, 21, 62, 11, 65, 81, 138, 44, 232, 221, 61, 121, 68, 250, 134, 52, 99, 169, 125,
+        ],
+    ), // 0xe91D153E0b41518A2Ce8Dd3D7944Fa863463a97d, WXDAI on Gnosis
+    (
+        137,
+        [
+            13, 80, 11, 29, 142, 142, 243, 30, 33, 201, 157, 29, 185, 166, 68, 77, 58, 223, 18, 112,
+        ],
+    ), // 0x0d500B1d8E8eF31E21C99d1Db9A6444d3ADf1270, WMATIC on Polygon
+    (
+        8453,
+        [66, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 6],
+    ), // 0x4200000000000000000000000000000000000006, WETH on Base
+    (
+        43114,
+        [
+            179, 31, 102, 170, 60, 30, 120, 83, 99, 240, 135, 90, 27, 116, 226, 123, 133, 253, 102,
+            199,
+        ],
+    ), // 0xB31f66AA3C1e785363F0875A1B74E27b85FD66c7, WAVAX on Avalanche
+    (
+        11155111,
+        [
+            255, 249, 151, 103, 130, 212, 108, 192, 86, 48, 209, 246, 235, 171, 24, 178, 50, 77,
+            107, 20,
+        ],
+    ), // 0xfFf9976782d46CC05630D1f6eBAb18b2324d6B14, WETH on Sepolia
 ];
+
+// ERC-165 INTERFACE IDS
+pub const ERC721_INTERFACE_ID: [u8; 4] = [128, 172, 88, 205]; // 0x80ac58cd
+pub const ERC1155_INTERFACE_ID: [u8; 4] = [217, 182, 122, 38]; // 0xd9b67a26
+
+// REVERT SELECTORS
+pub const ERROR_SELECTOR: [u8; 4] = [8, 195, 121, 160]; // 0x08c379a0, Error(string)
+pub const PANIC_SELECTOR: [u8; 4] = [78, 72, 123, 113]; // 0x4e487b71, Panic(uint256)
@@ -0,0 +1,293 @@
+use async_trait::async_trait;
+use ethers::{
+    providers::{Http, Middleware, Provider},
+    types::{BlockNumber, H256},
+};
+use std::sync::Arc;
+
+pub use super::abi_decoder::AbiDecoder;
+use super::call_tree::NativeTransfer;
+use super::error::Result;
+use super::process_logs::{native_transfer, InterfaceCache};
+pub use super::process_logs::{Erc1155Decoder, Erc20Decoder, Erc721Decoder};
+pub use super::seaport_decoder::SeaportDecoder;
+pub use super::swap_decoders::{BalancerDecoder, CurveDecoder, UniswapV2Decoder, UniswapV3Decoder};
+use super::types::{CustomEvent, RawLog, Sale, SimulationResults, Swap};
+pub use super::vault_decoders::{Erc4626Decoder, WethDecoder};
+
+// Turns the logs of an event into results. Implement it for your own contracts and register it in the
+// `DecoderRegistry` passed to `simulate`.
+#[async_trait]
+pub trait EventDecoder: Send + Sync {
+    // the event signatures (topic0) this decoder handles
+    fn topics(&self) -> Vec<H256>;
+
+    // Nothing decoded hands the log over to the next decoder of its topic, a log that isn't what the decoder
+    // expected (e.g. another contract reusing the event signature) should be left alone that way.
+    async fn decode(
+        &self,
+        log: &RawLog,
+        context: &mut DecodeContext<'_>,
+    ) -> Result<Vec<DecodedEvent>>;
+}
+
+#[allow(clippy::large_enum_variant)]
+pub enum DecodedEvent {
+    // token movements and approvals, reported in `SimulationReport::results`
+    Token(SimulationResults),
+    // anything else, reported in `SimulationReport::custom_events`
+    Custom(CustomEvent),
+    // DEX swaps, reported in `SimulationReport::swaps`
+    Swap(Swap),
+    // nft marketplace sales, reported in `SimulationReport::sales`
+    Sale(Sale),
+}
+
+// The events decoded from the logs of a transaction, sorted by where they are reported
+#[derive(Debug, Default)]
+pub struct DecodedLogs {
+    pub results: Vec<SimulationResults>,
+    pub custom_events: Vec<CustomEvent>,
+    pub swaps: Vec<Swap>,
+    pub sales: Vec<Sale>,
+    // log_index of the logs no decoder handled
+    pub undecoded: Vec<usize>,
+}
+
+impl DecodedLogs {
+    // the events decoded from the log, if any
+    pub fn push_log(&mut self, log: &RawLog, events: Vec<DecodedEvent>) {
+        if events.is_empty() {
+            self.undecoded.push(log.log_index);
+        }
+        for event in events {
+            self.push(event);
+        }
+    }
+
+    pub fn push(&mut self, event: DecodedEvent) {
+        match event {
+            DecodedEvent::Token(result) => self.results.push(result),
+            DecodedEvent::Custom(custom_event) => self.custom_events.push(custom_event),
+            DecodedEvent::Swap(swap) => self.swaps.push(swap),
+            DecodedEvent::Sale(sale) => self.sales.push(sale),
+        }
+    }
+}
+
+// What decoders can use while decoding the logs of a transaction
+pub struct DecodeContext<'a> {
+    // connected to the fork the transaction ran on, or to the rpc when nothing was mined (trace mode, revm)
+    pub provider: &'a Provider<Http>,
+    // the block calls should read, right after the transaction on a fork, the block it ran on top of otherwise: the
+    // state the transaction left is gone then, so contracts it deployed can't be called
+    pub block: BlockNumber,
+    pub(crate) interfaces: InterfaceCache,
+    chain_id: Option<u64>,
+}
+
+impl<'a> DecodeContext<'a> {
+    pub fn new(provider: &'a Provider<Http>, block: BlockNumber) -> Self {
+        DecodeContext {
+            provider,
+            block,
+            interfaces: InterfaceCache::default(),
+            chain_id: None,
+        }
+    }
+
+    // the chain the transaction ran on, a fork keeps the id of the chain it forked
+    pub async fn chain_id(&mut self) -> Result<u64> {
+        if let Some(chain_id) = self.chain_id {
+            return Ok(chain_id);
+        }
+        let chain_id = self.provider.get_chainid().await?.as_u64();
+        self.chain_id = Some(chain_id);
+
+        Ok(chain_id)
+    }
+}
+
+// The decoders a simulation uses. `DecoderRegistry::default()` has the built-in ERC20, ERC721, ERC1155, WETH,
+// ERC4626, DEX swap and Seaport ones, `DecoderRegistry::new()` starts empty.
+#[derive(Clone)]
+pub struct DecoderRegistry {
+    decoders: Vec<Arc<dyn EventDecoder>>,
+}
+
+impl Default for DecoderRegistry {
+    fn default() -> Self {
+        DecoderRegistry::new()
+            .register(Erc20Decoder)
+            .register(Erc721Decoder)
+            .register(Erc1155Decoder)
+            .register(WethDecoder)
+            .register(Erc4626Decoder)
+            .register(UniswapV2Decoder)
+            .register(UniswapV3Decoder)
+            .register(CurveDecoder)
+            .register(BalancerDecoder)
+            .register(SeaportDecoder)
+    }
+}
+
+impl DecoderRegistry {
+    pub fn new() -> Self {
+        DecoderRegistry {
+            decoders: Vec::new(),
+        }
+    }
+
+    // decoders registered later are asked first, so a custom decoder can take over a built-in one's topics
+    pub fn register(mut self, decoder: impl EventDecoder + 'static) -> Self {
+        self.decoders.push(Arc::new(decoder));
+        self
+    }
+
+    // registers a decoder asked after every other one, whatever it handles the other decoders didn't want
+    pub fn register_fallback(mut self, decoder: impl EventDecoder + 'static) -> Self {
+        self.decoders.insert(0, Arc::new(decoder));
+        self
+    }
+
+    // the events of a single log, from the first decoder of its topic that decodes anything
+    pub async fn decode(
+        &self,
+        log: &RawLog,
+        context: &mut DecodeContext<'_>,
+    ) -> Result<Vec<DecodedEvent>> {
+        // anonymous events without any topic can't be decoded by signature
+        let topic0 = match log.topics.first() {
+            Some(topic0) => *topic0,
+            None => return Ok(Vec::new()),
+        };
+
+        for decoder in self.decoders.iter().rev() {
+            if !decoder.topics().contains(&topic0) {
+                continue;
+            }
+            let events = decoder.decode(log, context).await?;
+            if !events.is_empty() {
+                return Ok(events);
+            }
+        }
+
+        Ok(Vec::new())
+    }
+
+    // the events of every log of a transaction, in the order they were emitted
+    pub async fn decode_all(
+        &self,
+        logs: &[RawLog],
+        provider: &Provider<Http>,
+        block: BlockNumber,
+    ) -> Result<DecodedLogs> {
+        self.decode_with_transfers(logs, Vec::new(), provider, block)
+            .await
+    }
+
+    // like `decode_all`, with the native transfers reported in between the watched events they happened between
+    pub(crate) async fn decode_with_transfers(
+        &self,
+        logs: &[RawLog],
+        transfers: Vec<NativeTransfer>,
+        provider: &Provider<Http>,
+        block: BlockNumber,
+    ) -> Result<DecodedLogs> {
+        let mut context = DecodeContext::new(provider, block);
+        let mut decoded = DecodedLogs::default();
+        let mut transfers = transfers.into_iter().peekable();
+
+        for log in logs {
+            while let Some(t) = transfers.next_if(|t| t.logs_before <= log.log_index) {
+                decoded
+                    .results
+                    .push(native_transfer(t.from, t.to, t.amount));
+            }
+            let events = self.decode(log, &mut context).await?;
+            decoded.push_log(log, events);
+        }
+        decoded
+            .results
+            .extend(transfers.map(|t| native_transfer(t.from, t.to, t.amount)));
+
+        Ok(decoded)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ethers::types::{Address, Bytes};
+    use std::convert::TryFrom;
+
+    // decodes every log of its topic into an event named after it, unless it is from `skipped`
+    struct NamedDecoder {
+        name: &'static str,
+        skipped: Address,
+    }
+
+    #[async_trait]
+    impl EventDecoder for NamedDecoder {
+        fn topics(&self) -> Vec<H256> {
+            vec![H256::repeat_byte(1)]
+        }
+
+        async fn decode(
+            &self,
+            log: &RawLog,
+            _context: &mut DecodeContext<'_>,
+        ) -> Result<Vec<DecodedEvent>> {
+            if log.address == self.skipped {
+                return Ok(Vec::new());
+            }
+            Ok(vec![DecodedEvent::Custom(CustomEvent {
+                log_index: log.log_index,
+                address: log.address,
+                name: self.name.to_owned(),
+                fields: Vec::new(),
+            })])
+        }
+    }
+
+    #[tokio::test]
+    async fn later_decoders_go_first_and_pass_on_what_they_skip() {
+        let provider = Provider::<Http>::try_from("http://localhost:8545").unwrap();
+        let (vault, pool) = (Address::repeat_byte(0xaa), Address::repeat_byte(0xbb));
+        let registry = DecoderRegistry::new()
+            .register(NamedDecoder {
+                name: "generic",
+                skipped: Address::zero(),
+            })
+            .register(NamedDecoder {
+                name: "vault",
+                skipped: pool,
+            });
+        let log = |log_index: usize, address: Address, topic: H256| RawLog {
+            log_index,
+            address,
+            topics: vec![topic],
+            data: Bytes::new(),
+        };
+        let logs = vec![
+            log(0, vault, H256::repeat_byte(1)),
+            log(1, pool, H256::repeat_byte(1)),
+            // no decoder for this one
+            log(2, vault, H256::repeat_byte(2)),
+        ];
+
+        let decoded = registry
+            .decode_all(&logs, &provider, BlockNumber::Latest)
+            .await
+            .unwrap();
+
+        assert!(decoded.results.is_empty());
+        assert_eq!(decoded.undecoded, vec![2]);
+        let decoded: Vec<(usize, &str)> = decoded
+            .custom_events
+            .iter()
+            .map(|event| (event.log_index, event.name.as_str()))
+            .collect();
+        assert_eq!(decoded, vec![(0, "vault"), (1, "generic")]);
+    }
+}
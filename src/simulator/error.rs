@@ -0,0 +1,83 @@
+use ethers::{
+    abi,
+    contract::AbiError,
+    providers::{ProviderError, RpcError},
+    types::Bytes,
+};
+use thiserror::Error;
+
+pub type Result<T, E = SimulationError> = std::result::Result<T, E>;
+
+// Every failure the simulator can run into. Nothing in the library exits the process, it's up to the caller
+// (the cli in `main.rs`) to decide what to do with these.
+#[derive(Debug, Error)]
+pub enum SimulationError {
+    // the transaction reverted or was rejected by the node, `data` holds the raw revert data when the node returned any
+    #[error("transaction reverted: {message}")]
+    Reverted {
+        message: String,
+        data: Option<Bytes>,
+    },
+    #[error("rpc request failed: {0}")]
+    Rpc(String),
+    #[error("could not spawn anvil: {0}")]
+    AnvilSpawn(String),
+    #[error("decoding failed: {0}")]
+    Decode(String),
+    #[error("unsupported trace: {0}")]
+    UnsupportedTrace(String),
+    #[error("invalid configuration: {0}")]
+    Config(String),
+}
+
+impl SimulationError {
+    // a failed `eth_sendTransaction`/`debug_traceCall` is almost always the node refusing a reverting transaction,
+    // so keep whatever revert data it attached to the json-rpc error
+    pub fn from_rpc_revert(err: ProviderError) -> Self {
+        let data = err
+            .as_error_response()
+            .and_then(|e| e.data.as_ref())
+            .and_then(|d| d.as_str())
+            .and_then(|d| d.parse::<Bytes>().ok());
+
+        match err.as_error_response() {
+            Some(e) => SimulationError::Reverted {
+                message: e.message.clone(),
+                data,
+            },
+            None => SimulationError::Rpc(err.to_string()),
+        }
+    }
+
+    // revert data to report for a node rejection that is really a revert, `None` if the transaction was rejected for
+    // another reason (e.g. insufficient funds) or this isn't a revert at all
+    pub fn revert_data(&self) -> Option<Bytes> {
+        match self {
+            SimulationError::Reverted {
+                data: Some(data), ..
+            } => Some(data.clone()),
+            SimulationError::Reverted { message, .. } if message.contains("revert") => {
+                Some(Bytes::new())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<ProviderError> for SimulationError {
+    fn from(err: ProviderError) -> Self {
+        SimulationError::Rpc(err.to_string())
+    }
+}
+
+impl From<abi::Error> for SimulationError {
+    fn from(err: abi::Error) -> Self {
+        SimulationError::Decode(err.to_string())
+    }
+}
+
+impl From<AbiError> for SimulationError {
+    fn from(err: AbiError) -> Self {
+        SimulationError::Decode(err.to_string())
+    }
+}
@@ -1,60 +1,119 @@
 use ethers::{
     core::types::TransactionRequest,
     providers::{Http, Middleware, Provider},
+    types::{BlockNumber, TransactionReceipt, H256, U256, U64},
 };
-use eyre::Result;
-use std::process;
 
-use super::process_logs::process_logs;
-use super::types::{MyLog, SimulationResults};
+use super::decoder::DecoderRegistry;
+use super::error::{Result, SimulationError};
+use super::trace_simulator;
+use super::types::{RawLog, SimulationReport};
 
 pub async fn simulate(
     tx: TransactionRequest,
     provider: &Provider<Http>,
-) -> Result<Vec<SimulationResults>> {
+    decoders: &DecoderRegistry,
+) -> Result<SimulationReport> {
     // send tx
-    let pending_tx = provider
-        .send_transaction(tx, None)
-        .await
-        .unwrap_or_else(|e| {
-            eprintln!("transaction reverted with err: {}", e);
-            process::exit(1);
-        });
+    let pending_tx = match provider.send_transaction(tx, None).await {
+        Ok(pending_tx) => pending_tx,
+        Err(e) => {
+            let err = SimulationError::from_rpc_revert(e);
+            return match err.revert_data() {
+                Some(data) => Ok(SimulationReport::reverted(data, None)),
+                None => Err(err),
+            };
+        }
+    };
 
     // await and get receipt and tx
     let receipt = pending_tx
         .await?
-        .ok_or_else(|| eyre::format_err!("Transaction Failed"))?;
-
-    // let tx = provider.get_transaction(receipt.transaction_hash).await?;
-
-    // println!("tx: {:?}", serde_json::to_string(&tx)?);
-    // println!("receipt: {:?}", serde_json::to_string(&receipt)?);
-
-    let logs = receipt.logs;
-    // println!("logs: {:?}", logs);
-
-    let mut simulated_infos: Vec<SimulationResults> = Vec::new();
-
-    for log in logs.into_iter() {
-        match process_logs(
-            MyLog {
-                address: log.address,
-                topics: log.topics,
-                data: log.data,
-            },
-            provider.clone(),
-        )
-        .await
-        {
-            Ok(Some(x)) => simulated_infos.push(x),
-            Ok(None) => {}
-            Err(err) => {
-                eprintln!("Err {}", err);
-                process::exit(1)
-            }
-        }
-    }
+        .ok_or_else(|| SimulationError::Rpc("transaction was dropped from the fork".to_owned()))?;
+
+    report(receipt, provider, decoders).await
+}
+
+// Sends the transaction to a fork that isn't automining, so it's mined last in a single block along with the
+// transactions queued before it
+pub async fn simulate_queued(
+    tx: TransactionRequest,
+    provider: &Provider<Http>,
+    decoders: &DecoderRegistry,
+) -> Result<SimulationReport> {
+    let tx_hash = queue(tx, provider).await?;
+    mine_block(provider).await?;
+
+    let receipt = provider
+        .get_transaction_receipt(tx_hash)
+        .await?
+        .ok_or_else(|| SimulationError::Rpc("transaction was left out of the block".to_owned()))?;
+
+    report(receipt, provider, decoders).await
+}
+
+async fn report(
+    receipt: TransactionReceipt,
+    provider: &Provider<Http>,
+    decoders: &DecoderRegistry,
+) -> Result<SimulationReport> {
+    let logs: Vec<RawLog> = receipt
+        .logs
+        .into_iter()
+        .enumerate()
+        .map(|(log_index, log)| RawLog {
+            log_index,
+            address: log.address,
+            topics: log.topics,
+            data: log.data,
+        })
+        .collect();
+
+    // right after the transaction, a batch mines the next ones on top of it
+    let block = receipt
+        .block_number
+        .map_or(BlockNumber::Latest, BlockNumber::Number);
+    // the receipt doesn't say where ETH went, the fork's call tracer does
+    let call_tree = trace_simulator::mined_call_tree(receipt.transaction_hash, provider).await;
+    let transfers = call_tree
+        .as_ref()
+        .map(|tree| tree.flatten().1)
+        .unwrap_or_default();
+    let decoded = decoders
+        .decode_with_transfers(&logs, transfers, provider, block)
+        .await?;
+
+    Ok(SimulationReport {
+        results: decoded.results,
+        success: receipt.status == Some(U64::from(1)),
+        gas_used: receipt.gas_used.unwrap_or_default(),
+        effective_gas_price: receipt.effective_gas_price,
+        block_number: receipt.block_number.map(|n| n.as_u64()),
+        tx_hash: Some(receipt.transaction_hash),
+        logs,
+        revert_data: None,
+        revert_reason: None,
+        rejection: None,
+        call_tree,
+        custom_events: decoded.custom_events,
+        swaps: decoded.swaps,
+        sales: decoded.sales,
+        undecoded_logs: decoded.undecoded,
+        asset_changes: Vec::new(),
+        balance_checks: None,
+        balance_checks_skipped: None,
+    })
+}
+
+// sends the transaction without mining it, the fork's automine has to be off
+pub async fn queue(tx: TransactionRequest, provider: &Provider<Http>) -> Result<H256> {
+    Ok(*provider.send_transaction(tx, None).await?)
+}
+
+// mines whatever was queued into a single block and goes back to mining every transaction as it comes
+pub async fn mine_block(provider: &Provider<Http>) -> Result<()> {
+    provider.request::<_, U256>("evm_mine", ()).await?;
+    provider.request::<_, ()>("evm_setAutomine", [true]).await?;
 
-    Ok(simulated_infos)
+    Ok(())
 }
@@ -1,95 +1,372 @@
 use dotenv::dotenv;
 use ethers::{
+    abi::Abi,
     core::types::TransactionRequest,
     providers::{Http, Provider},
-    types::Address,
-    utils::Anvil,
+    types::{Address, H256},
+    utils::{Anvil, AnvilInstance},
 };
-use eyre::Result;
 use std::convert::TryFrom;
-use std::process;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
 
+mod abi_decoder;
+mod abi_loader;
+mod asset_changes;
+mod call_tree;
 mod constants;
+pub mod decoder;
+pub mod error;
 mod fork_simulator;
+mod parity_trace;
 pub mod print_result;
 mod process_logs;
+mod reconcile;
+mod replay;
+mod revert;
+mod revm_simulator;
+mod seaport_decoder;
+mod swap_decoders;
+#[cfg(test)]
+mod test_utils;
 mod trace_simulator;
 pub mod types;
 mod utils;
+mod vault_decoders;
 
-use types::{SimulationParams, SimulationResults};
+use decoder::DecoderRegistry;
+use error::{Result, SimulationError};
+use revm_simulator::RevmFork;
+use types::{BatchReport, BatchSummary, ReplayOptions, SimulationParams, SimulationReport};
 
-use self::types::BlockNumberType;
+use self::types::{BlockNumberType, SimulationBackend, SkippedBalanceChecks};
 
+// Simulates the transaction and decodes its logs with `decoders`, `DecoderRegistry::default()` has the built-in token
+// decoders
 pub async fn simulate(
     simulation_params: SimulationParams,
     create_fork: bool,
-) -> Result<Vec<SimulationResults>> {
-    // either use parsed in rpc-url if it exists or use the one in the nev file if that exists, else revert
-    let rpc_url = match simulation_params.rpc_url {
-        Some(u) => u,
-        None => {
-            dotenv().ok();
-            let url =
-                std::env::var("RPC_URL").expect("RPC_URL must be set if rpc flag is not given");
-            url
-        }
-    };
+    decoders: &DecoderRegistry,
+) -> Result<SimulationReport> {
+    let rpc_url = resolve_rpc_url(&simulation_params.rpc_url)?;
 
-    let tx = TransactionRequest::new()
-        .from(simulation_params.from)
-        .to(simulation_params.to)
-        .value(simulation_params.value)
-        .data(simulation_params.data);
+    // load abis up front so a bad path fails before we spend time forking
+    let abis = abi_loader::load_abis(&simulation_params.abi_paths)?;
+    let decoders = &decoders
+        .clone()
+        .register_fallback(decoder::AbiDecoder::new(&abis));
+
+    let tx = transaction_request(&simulation_params);
+
+    // the preceding transactions of a position are fetched from the chain itself, the fork doesn't have that block
+    let preceding =
+        replay::preceding_transactions(&http_provider(&rpc_url)?, &simulation_params.block_number)
+            .await?;
 
     let provider;
     let anvil;
-    let simulated_infos: Vec<SimulationResults>;
-    if simulation_params.persist {
-        provider = Provider::<Http>::try_from(rpc_url).unwrap_or_else(|_| {
-            eprintln!("could not instantiate HTTP Provider");
-            process::exit(1);
-        });
-
-        simulated_infos = use_fork_simulator(&provider, simulation_params.from, tx).await?;
+    let mut report: SimulationReport;
+    if simulation_params.backend == SimulationBackend::Revm {
+        // the in-process backend reads straight from the rpc and never touches an anvil instance
+        provider = http_provider(&rpc_url)?;
+
+        let mut fork = RevmFork::new(&provider, &simulation_params.block_number).await?;
+        for preceding_tx in preceding.into_iter().flat_map(|prefix| prefix.transactions) {
+            fork.apply(preceding_tx)?;
+        }
+        report = fork.simulate(tx, false, decoders).await?;
+    } else if simulation_params.persist {
+        provider = http_provider(&rpc_url)?;
+
+        report = use_fork_simulator(&provider, simulation_params.from, tx, decoders).await?;
+    } else if !create_fork && preceding.is_none() {
+        // traced by the rpc itself, whose tracing namespaces are the ones to pick from
+        provider = http_provider(&rpc_url)?;
+
+        report = trace_simulator::simulate(
+            tx,
+            &provider,
+            simulation_params.block_number,
+            simulation_params.trace_namespace,
+            decoders,
+        )
+        .await?;
     } else {
         // create instance of forked chain using anvil
-        anvil = match simulation_params.block_number {
-            BlockNumberType::Past(num) => Anvil::new().fork(rpc_url).fork_block_number(num).spawn(),
-            BlockNumberType::Latest => Anvil::new().fork(rpc_url).spawn(),
-        };
-        provider = Provider::<Http>::try_from(anvil.endpoint()).unwrap_or_else(|_| {
-            eprintln!("could not instantiate HTTP Provider");
-            process::exit(1);
-        });
+        anvil = spawn_anvil(rpc_url, &simulation_params.block_number)?;
+        provider = http_provider(&anvil.endpoint())?;
+
+        // at a position within a block the transaction joins the ones queued before it in that block
+        let queued = preceding.is_some();
+        if let Some(prefix) = preceding {
+            queue_on_fork(&provider, prefix).await?;
+        }
 
-        simulated_infos = if create_fork {
-            use_fork_simulator(&provider, simulation_params.from, tx).await?
+        report = if !create_fork {
+            // the preceding transactions only exist on the fork, so that's where the transaction is traced, against
+            // the state they leave
+            fork_simulator::mine_block(&provider).await?;
+            trace_simulator::simulate(
+                tx,
+                &provider,
+                BlockNumberType::Latest,
+                simulation_params.trace_namespace,
+                decoders,
+            )
+            .await?
+        } else if queued {
+            impersonating(
+                &provider,
+                simulation_params.from,
+                fork_simulator::simulate_queued(tx, &provider, decoders),
+            )
+            .await?
         } else {
-            trace_simulator::simulate(tx, &provider, simulation_params.block_number)
-                .await
-                .expect("Fork simulation failed")
+            use_fork_simulator(&provider, simulation_params.from, tx, decoders).await?
+        };
+    }
+
+    decode_revert_reason(&mut report, &abis);
+    process_logs::classify_supply_changes(&mut report.results, &simulation_params.burn_addresses);
+    report.asset_changes = asset_changes::asset_changes(&report, &simulation_params);
+    check_balances(&mut report, &provider).await;
+
+    Ok(report)
+}
+
+// Re-runs an already mined transaction, see `ReplayOptions` for where it runs
+pub async fn replay(
+    tx_hash: H256,
+    options: ReplayOptions,
+    create_fork: bool,
+    decoders: &DecoderRegistry,
+) -> Result<SimulationReport> {
+    let provider = http_provider(&resolve_rpc_url(&options.rpc_url)?)?;
+    let simulation_params = replay::replay_params(&provider, tx_hash, options).await?;
+
+    simulate(simulation_params, create_fork, decoders).await
+}
+
+// Simulates the transactions one after the other on a single fork, so each one sees the state changes of the ones
+// before it. The fork is configured by the first entry's block, rpc, persist, backend and abi settings, every other
+// entry has to agree with them. Reverting transactions are reported and leave the state untouched.
+pub async fn simulate_batch(
+    batch: Vec<SimulationParams>,
+    decoders: &DecoderRegistry,
+) -> Result<BatchReport> {
+    let first = batch
+        .first()
+        .ok_or_else(|| SimulationError::Config("batch has no transactions".to_owned()))?;
+    let same_fork = batch.iter().all(|params| {
+        params.block_number == first.block_number
+            && params.rpc_url == first.rpc_url
+            && params.persist == first.persist
+            && params.backend == first.backend
+            && params.abi_paths == first.abi_paths
+    });
+    if !same_fork {
+        return Err(SimulationError::Config(
+            "every transaction of a batch has to run on the same fork".to_owned(),
+        ));
+    }
+
+    let rpc_url = resolve_rpc_url(&first.rpc_url)?;
+    let abis = abi_loader::load_abis(&first.abi_paths)?;
+    let decoders = &decoders
+        .clone()
+        .register_fallback(decoder::AbiDecoder::new(&abis));
+
+    let preceding =
+        replay::preceding_transactions(&http_provider(&rpc_url)?, &first.block_number).await?;
+
+    let provider;
+    let anvil;
+    let mut revm_fork = None;
+    if first.backend == SimulationBackend::Revm {
+        provider = http_provider(&rpc_url)?;
+        let mut fork = RevmFork::new(&provider, &first.block_number).await?;
+        for preceding_tx in preceding.into_iter().flat_map(|prefix| prefix.transactions) {
+            fork.apply(preceding_tx)?;
+        }
+        revm_fork = Some(fork);
+    } else if first.persist {
+        provider = http_provider(&rpc_url)?;
+    } else {
+        anvil = spawn_anvil(rpc_url, &first.block_number)?;
+        provider = http_provider(&anvil.endpoint())?;
+        if let Some(prefix) = preceding {
+            queue_on_fork(&provider, prefix).await?;
+            fork_simulator::mine_block(&provider).await?;
+        }
+    }
+
+    let mut reports: Vec<SimulationReport> = Vec::new();
+    for simulation_params in batch.iter() {
+        let tx = transaction_request(simulation_params);
+
+        let simulated = match revm_fork.as_mut() {
+            Some(fork) => fork.simulate(tx, true, decoders).await,
+            None => use_fork_simulator(&provider, simulation_params.from, tx, decoders).await,
         };
+        // a transaction the node or the evm refuses to run is that transaction's failure, not the batch's
+        let mut report = match simulated {
+            Err(SimulationError::Reverted { message, .. }) => {
+                reports.push(SimulationReport::rejected(message, None));
+                continue;
+            }
+            simulated => simulated?,
+        };
+        decode_revert_reason(&mut report, &abis);
+        process_logs::classify_supply_changes(
+            &mut report.results,
+            &simulation_params.burn_addresses,
+        );
+        report.asset_changes = asset_changes::asset_changes(&report, simulation_params);
+        check_balances(&mut report, &provider).await;
+
+        reports.push(report);
     }
 
-    Ok(simulated_infos)
+    Ok(BatchReport {
+        summary: BatchSummary::from_reports(&reports),
+        reports,
+    })
+}
+
+fn decode_revert_reason(report: &mut SimulationReport, abis: &[Abi]) {
+    report.revert_reason = report
+        .revert_data
+        .as_ref()
+        .and_then(|data| revert::decode_revert(data, abis));
+}
+
+// Only transactions mined on the fork leave the blocks before and after them to read balances from. The checks are
+// only a cross-check of the report, a fork that can't serve the reads (e.g. no multicall) leaves them out rather than
+// failing the simulation, `balance_checks_skipped` says why they are missing.
+async fn check_balances(report: &mut SimulationReport, provider: &Provider<Http>) {
+    let checked = match (report.tx_hash, report.block_number) {
+        (Some(_), Some(block)) => match reconcile::mined_alone(provider, block).await {
+            Ok(true) => reconcile::balance_checks(report, provider, block)
+                .await
+                .map_err(|_| SkippedBalanceChecks::ReadsFailed),
+            Ok(false) => Err(SkippedBalanceChecks::SharedBlock),
+            Err(_) => Err(SkippedBalanceChecks::ReadsFailed),
+        },
+        _ => Err(SkippedBalanceChecks::NotMined),
+    };
+    match checked {
+        Ok(checks) => report.balance_checks = Some(checks),
+        Err(skipped) => report.balance_checks_skipped = Some(skipped),
+    }
+}
+
+fn transaction_request(simulation_params: &SimulationParams) -> TransactionRequest {
+    let mut tx = TransactionRequest::new()
+        .from(simulation_params.from)
+        .to(simulation_params.to)
+        .value(simulation_params.value)
+        .data(simulation_params.data.clone());
+    if let Some(gas_limit) = simulation_params.gas_limit {
+        tx = tx.gas(gas_limit);
+    }
+    if let Some(gas_price) = simulation_params.gas_price {
+        tx = tx.gas_price(gas_price);
+    }
+    tx
+}
+
+// either use parsed in rpc-url if it exists or use the one in the env file if that exists, else revert
+fn resolve_rpc_url(rpc_url: &Option<String>) -> Result<String> {
+    match rpc_url {
+        Some(u) => Ok(u.clone()),
+        None => {
+            dotenv().ok();
+            std::env::var("RPC_URL").map_err(|_| {
+                SimulationError::Config("RPC_URL must be set if rpc flag is not given".to_owned())
+            })
+        }
+    }
+}
+
+// create instance of forked chain using anvil
+fn spawn_anvil(rpc_url: String, block_number: &BlockNumberType) -> Result<AnvilInstance> {
+    // queued transactions are mined in the order they were sent rather than by their tip, like a block's own order
+    let anvil = Anvil::new().fork(rpc_url).args(["--order", "fifo"]);
+    let anvil_builder = match block_number.fork_block() {
+        Some(num) => anvil.fork_block_number(num),
+        None => anvil,
+    };
+
+    // `Anvil::spawn` panics when the binary is missing or fails to start, surface that as an error instead
+    panic::catch_unwind(AssertUnwindSafe(|| anvil_builder.spawn())).map_err(|e| {
+        let reason = e
+            .downcast_ref::<String>()
+            .cloned()
+            .or_else(|| e.downcast_ref::<&str>().map(|s| s.to_string()))
+            .unwrap_or_else(|| "is anvil installed and in your PATH?".to_owned());
+        SimulationError::AnvilSpawn(reason)
+    })
 }
 
 async fn use_fork_simulator(
     provider: &Provider<Http>,
     from: Address,
     tx: TransactionRequest,
-) -> Result<Vec<SimulationResults>> {
+    decoders: &DecoderRegistry,
+) -> Result<SimulationReport> {
+    impersonating(
+        provider,
+        from,
+        fork_simulator::simulate(tx, provider, decoders),
+    )
+    .await
+}
+
+// Queues the transactions mined before the position, each one sent by its own (impersonated) sender, with automine
+// off so they all land in a single block. Like the original block it follows the fork block and carries its timestamp
+// and coinbase. Whatever is sent next joins that block until `fork_simulator::mine_block` mines it.
+async fn queue_on_fork(provider: &Provider<Http>, prefix: replay::BlockPrefix) -> Result<()> {
+    provider
+        .request::<_, ()>("evm_setAutomine", [false])
+        .await?;
+    provider
+        .request::<_, ()>("evm_setNextBlockTimestamp", [prefix.timestamp.as_u64()])
+        .await?;
+    if let Some(coinbase) = prefix.coinbase {
+        provider
+            .request::<_, ()>("anvil_setCoinbase", [coinbase])
+            .await?;
+    }
+
+    for tx in prefix.transactions {
+        let from = tx.from.unwrap_or_default();
+        impersonating(provider, from, fork_simulator::queue(tx, provider)).await?;
+    }
+
+    Ok(())
+}
+
+async fn impersonating<T>(
+    provider: &Provider<Http>,
+    from: Address,
+    action: impl Future<Output = Result<T>>,
+) -> Result<T> {
     // impersonate address
-    provider.request("anvil_impersonateAccount", [from]).await?;
+    provider
+        .request::<_, ()>("anvil_impersonateAccount", [from])
+        .await?;
 
-    let simulated_infos = fork_simulator::simulate(tx, &provider)
-        .await
-        .expect("Fork simulation failed");
+    let result = action.await;
 
+    // stop impersonating even if the action failed so a persisted fork isn't left in a weird state
     provider
-        .request("anvil_stopImpersonatingAccount", [from])
+        .request::<_, ()>("anvil_stopImpersonatingAccount", [from])
         .await?;
 
-    Ok(simulated_infos)
+    result
+}
+
+fn http_provider(url: &str) -> Result<Provider<Http>> {
+    Provider::<Http>::try_from(url)
+        .map_err(|e| SimulationError::Config(format!("could not instantiate HTTP Provider: {}", e)))
 }
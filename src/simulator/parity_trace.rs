@@ -0,0 +1,407 @@
+use ethers::{
+    core::types::TransactionRequest,
+    providers::{Http, Middleware, Provider},
+    types::{
+        Action, Address, BlockNumber, Bytes, CallType, ExecutedInstruction, Res, TraceType,
+        TransactionTrace, VMTrace, H256, U256,
+    },
+};
+
+use super::error::{Result, SimulationError};
+use super::types::{CallFrame, CallKind, FrameLog};
+use super::utils::u256_to_address;
+
+// Traces the transaction with the parity style `trace_call` served by Erigon, Nethermind and Reth. Its traces don't
+// carry logs, those are rebuilt from the `vmTrace` of the same call.
+pub async fn trace_call(
+    tx: TransactionRequest,
+    provider: &Provider<Http>,
+    block: BlockNumber,
+) -> Result<CallFrame> {
+    let block_trace = provider
+        .trace_call(tx, vec![TraceType::Trace, TraceType::VmTrace], Some(block))
+        .await
+        .map_err(SimulationError::from_rpc_revert)?;
+    let traces = block_trace.trace.ok_or_else(|| {
+        SimulationError::UnsupportedTrace("trace_call returned no trace".to_owned())
+    })?;
+    let vm_trace = block_trace.vm_trace.ok_or_else(|| {
+        SimulationError::UnsupportedTrace(
+            "trace_call returned no vmTrace to rebuild the logs from".to_owned(),
+        )
+    })?;
+
+    let mut call_tree = call_tree(traces)?;
+    let address = call_tree.to;
+    frame_logs(&mut call_tree, &vm_trace, address)?;
+    // a failed top call has no result, its revert data is the transaction's output
+    call_tree.output = block_trace.output;
+
+    Ok(call_tree)
+}
+
+// The traces come as a flat list in execution order, each one locating itself in the tree with its `traceAddress`:
+// the index of the subcall to follow at every level below the top call.
+fn call_tree(traces: Vec<TransactionTrace>) -> Result<CallFrame> {
+    let mut traces = traces.into_iter();
+    let mut call_tree = match traces.next() {
+        Some(trace) if trace.trace_address.is_empty() => call_frame(trace)?,
+        _ => {
+            return Err(SimulationError::UnsupportedTrace(
+                "trace_call didn't start with the top call".to_owned(),
+            ))
+        }
+    };
+
+    for trace in traces {
+        let misplaced = || {
+            SimulationError::UnsupportedTrace(format!(
+                "trace at {:?} doesn't follow its parent's previous subcall",
+                trace.trace_address
+            ))
+        };
+        let (index, path) = trace.trace_address.split_last().ok_or_else(misplaced)?;
+
+        let mut parent = &mut call_tree;
+        for i in path {
+            parent = parent.calls.get_mut(*i).ok_or_else(misplaced)?;
+        }
+        if *index != parent.calls.len() {
+            return Err(misplaced());
+        }
+
+        let frame = call_frame(trace)?;
+        parent.calls.push(frame);
+    }
+
+    Ok(call_tree)
+}
+
+// Rebuilds the LOG0 to LOG4 of a frame, emitted as `address`, by replaying its operations on a model of its stack and
+// memory: the vm trace only tells what every operation pushed and wrote. A subcall is matched with the frame's next
+// child call to the same target, calls into precompiles and accounts without code not having a vm trace of their own.
+fn frame_logs(frame: &mut CallFrame, vm_trace: &VMTrace, address: Address) -> Result<()> {
+    let mut stack: Vec<U256> = Vec::new();
+    let mut memory: Vec<u8> = Vec::new();
+    let mut next_call = 0;
+
+    for op in vm_trace.ops.iter() {
+        let opcode = opcode(&op.op)?;
+        // the frame halted on this operation
+        let executed = match &op.ex {
+            Some(executed) => executed,
+            None => break,
+        };
+        let inputs = stack_inputs(opcode);
+        let args = stack
+            .len()
+            .checked_sub(inputs)
+            .map(|start| stack.split_off(start))
+            .ok_or_else(|| {
+                SimulationError::UnsupportedTrace(format!(
+                    "opcode 0x{:02x} with only {} stack items",
+                    opcode,
+                    stack.len()
+                ))
+            })?;
+        // the i-th item from the top of the stack
+        let arg = |i: usize| args[args.len() - 1 - i];
+
+        // the code the call runs and the address it runs as
+        let call = match opcode {
+            0xa0..=0xa4 => {
+                let topics = (0..(opcode - 0xa0) as usize)
+                    .map(|i| {
+                        let mut topic = H256::zero();
+                        arg(2 + i).to_big_endian(topic.as_bytes_mut());
+                        topic
+                    })
+                    .collect();
+                frame.logs.push(FrameLog {
+                    address,
+                    topics,
+                    data: read_memory(&memory, arg(0), arg(1))?,
+                    position: next_call,
+                });
+                None
+            }
+            // CALL, STATICCALL
+            0xf1 | 0xfa => Some((u256_to_address(arg(1)), u256_to_address(arg(1)))),
+            // CALLCODE, DELEGATECALL
+            0xf2 | 0xf4 => Some((u256_to_address(arg(1)), address)),
+            // CREATE, CREATE2, the created address is what they push
+            0xf0 | 0xf5 => executed.push.last().map(|created| {
+                let created = u256_to_address(*created);
+                (created, created)
+            }),
+            // SELFDESTRUCT, to its beneficiary
+            0xff => Some((u256_to_address(arg(0)), u256_to_address(arg(0)))),
+            _ => None,
+        };
+        if let Some((target, runs_as)) = call {
+            let child = match &op.sub {
+                Some(_) => frame.calls[next_call.min(frame.calls.len())..]
+                    .iter()
+                    .position(|call| call.to == target)
+                    .map(|i| next_call + i),
+                None => frame
+                    .calls
+                    .get(next_call)
+                    .filter(|call| call.to == target)
+                    .map(|_| next_call),
+            };
+            if let Some(child) = child {
+                next_call = child + 1;
+                if let Some(sub) = &op.sub {
+                    frame_logs(&mut frame.calls[child], sub, runs_as)?;
+                }
+            }
+        }
+
+        if let Some(written) = &executed.mem {
+            let end = written.off + written.data.len();
+            if memory.len() < end {
+                memory.resize(end, 0);
+            }
+            memory[written.off..end].copy_from_slice(&written.data);
+        }
+        stack.extend(executed.push.iter().copied());
+    }
+
+    Ok(())
+}
+
+fn opcode(instruction: &ExecutedInstruction) -> Result<u8> {
+    match instruction {
+        ExecutedInstruction::Known(opcode) => Ok(u8::from(*opcode)),
+        // opcodes newer than the ones ethers knows
+        ExecutedInstruction::Unknown(name) => match name.as_str() {
+            "KECCAK256" => Ok(0x20),
+            "BLOBHASH" => Ok(0x49),
+            "BLOBBASEFEE" => Ok(0x4a),
+            "TLOAD" => Ok(0x5c),
+            "TSTORE" => Ok(0x5d),
+            "MCOPY" => Ok(0x5e),
+            _ => Err(SimulationError::UnsupportedTrace(format!(
+                "unknown opcode {} in vmTrace",
+                name
+            ))),
+        },
+    }
+}
+
+// How many items the opcode takes off the stack. DUPn and SWAPn are counted as taking the items they copy or swap
+// since the vm trace reports them as pushing all of them back.
+fn stack_inputs(opcode: u8) -> usize {
+    match opcode {
+        // ADDMOD, MULMOD
+        0x08 | 0x09 => 3,
+        // ISZERO, NOT
+        0x15 | 0x19 => 1,
+        0x01..=0x1d => 2,
+        // KECCAK256
+        0x20 => 2,
+        // BALANCE, CALLDATALOAD, EXTCODESIZE, EXTCODEHASH
+        0x31 | 0x35 | 0x3b | 0x3f => 1,
+        // CALLDATACOPY, CODECOPY, RETURNDATACOPY
+        0x37 | 0x39 | 0x3e => 3,
+        // EXTCODECOPY
+        0x3c => 4,
+        // BLOCKHASH, BLOBHASH
+        0x40 | 0x49 => 1,
+        // POP, MLOAD, SLOAD, JUMP, TLOAD
+        0x50 | 0x51 | 0x54 | 0x56 | 0x5c => 1,
+        // MSTORE, MSTORE8, SSTORE, JUMPI, TSTORE
+        0x52 | 0x53 | 0x55 | 0x57 | 0x5d => 2,
+        // MCOPY
+        0x5e => 3,
+        // DUP1 to DUP16
+        0x80..=0x8f => (opcode - 0x7f) as usize,
+        // SWAP1 to SWAP16
+        0x90..=0x9f => (opcode - 0x8e) as usize,
+        // LOG0 to LOG4
+        0xa0..=0xa4 => (opcode - 0x9e) as usize,
+        // CREATE
+        0xf0 => 3,
+        // CALL, CALLCODE
+        0xf1 | 0xf2 => 7,
+        // RETURN, REVERT
+        0xf3 | 0xfd => 2,
+        // DELEGATECALL, STATICCALL
+        0xf4 | 0xfa => 6,
+        // CREATE2
+        0xf5 => 4,
+        // SELFDESTRUCT
+        0xff => 1,
+        _ => 0,
+    }
+}
+
+// `size` bytes of the memory at `offset`, memory that was never written to reading as zeroes
+fn read_memory(memory: &[u8], offset: U256, size: U256) -> Result<Bytes> {
+    if size.is_zero() {
+        return Ok(Bytes::default());
+    }
+    if offset.bits() > 32 || size.bits() > 32 {
+        return Err(SimulationError::UnsupportedTrace(
+            "log data is out of the memory's range".to_owned(),
+        ));
+    }
+    let (offset, size) = (offset.as_usize(), size.as_usize());
+
+    let mut data = vec![0u8; size];
+    if offset < memory.len() {
+        let available = (memory.len() - offset).min(size);
+        data[..available].copy_from_slice(&memory[offset..offset + available]);
+    }
+    Ok(Bytes::from(data))
+}
+
+fn call_frame(trace: TransactionTrace) -> Result<CallFrame> {
+    let (gas_used, output, created) = match trace.result {
+        Some(Res::Call(result)) => (result.gas_used, result.output, None),
+        Some(Res::Create(result)) => (result.gas_used, result.code, Some(result.address)),
+        // failed frames have no result
+        Some(Res::None) | None => (Default::default(), Bytes::new(), None),
+    };
+    let reverted = trace.error.is_some();
+
+    let frame = match trace.action {
+        Action::Call(call) => CallFrame {
+            kind: match call.call_type {
+                CallType::StaticCall => CallKind::StaticCall,
+                CallType::DelegateCall => CallKind::DelegateCall,
+                CallType::CallCode => CallKind::CallCode,
+                CallType::Call | CallType::None => CallKind::Call,
+            },
+            from: call.from,
+            to: call.to,
+            value: call.value,
+            gas_used,
+            input: call.input,
+            output,
+            reverted,
+            calls: Vec::new(),
+            logs: Vec::new(),
+        },
+        // the trace doesn't tell CREATE and CREATE2 apart
+        Action::Create(create) => CallFrame {
+            kind: CallKind::Create,
+            from: create.from,
+            to: created.unwrap_or_default(),
+            value: create.value,
+            gas_used,
+            input: create.init,
+            output,
+            reverted,
+            calls: Vec::new(),
+            logs: Vec::new(),
+        },
+        Action::Suicide(suicide) => CallFrame {
+            kind: CallKind::SelfDestruct,
+            from: suicide.address,
+            to: suicide.refund_address,
+            value: suicide.balance,
+            gas_used,
+            input: Bytes::new(),
+            output,
+            reverted,
+            calls: Vec::new(),
+            logs: Vec::new(),
+        },
+        Action::Reward(_) => {
+            return Err(SimulationError::UnsupportedTrace(
+                "block rewards can't be part of a call".to_owned(),
+            ))
+        }
+    };
+
+    Ok(frame)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ethers::types::{Address, BlockTrace, U256};
+
+    #[test]
+    fn rebuilds_the_call_tree_from_trace_addresses() {
+        let block_trace: BlockTrace =
+            serde_json::from_str(include_str!("fixtures/parity_trace_call.json")).unwrap();
+        let (user, router, weth, created) = (
+            Address::repeat_byte(0x11),
+            Address::repeat_byte(0x77),
+            Address::repeat_byte(0xcc),
+            Address::repeat_byte(0xdd),
+        );
+
+        let call_tree = call_tree(block_trace.trace.unwrap()).unwrap();
+
+        let kinds: Vec<CallKind> = call_tree.calls.iter().map(|c| c.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                CallKind::Call,
+                CallKind::StaticCall,
+                CallKind::Create,
+                CallKind::Call
+            ]
+        );
+        assert_eq!(call_tree.calls[2].to, created);
+        assert_eq!(call_tree.calls[2].calls[0].kind, CallKind::SelfDestruct);
+        assert!(call_tree.calls[3].reverted);
+
+        let ether = U256::exp10(18);
+        let (logs, transfers) = call_tree.flatten();
+        assert!(logs.is_empty());
+        // the value sent by the reverted call and its subcall is gone
+        let transfers: Vec<(Address, Address, U256)> =
+            transfers.iter().map(|t| (t.from, t.to, t.amount)).collect();
+        assert_eq!(
+            transfers,
+            vec![
+                (user, router, ether),
+                (router, weth, ether),
+                (router, created, U256::from(5)),
+                (created, user, U256::from(5))
+            ]
+        );
+    }
+
+    #[test]
+    fn rebuilds_the_logs_from_the_vm_trace() {
+        let block_trace: BlockTrace =
+            serde_json::from_str(include_str!("fixtures/parity_vm_trace.json")).unwrap();
+        let (router, weth) = (Address::repeat_byte(0x77), Address::repeat_byte(0xcc));
+        let deposit: H256 = "0xe1fffcc4923d04b559f4d29a8bfc6cda04eb5b0d3c460751c2402c5c5cc9109c"
+            .parse()
+            .unwrap();
+
+        let mut call_tree = call_tree(block_trace.trace.unwrap()).unwrap();
+        frame_logs(&mut call_tree, &block_trace.vm_trace.unwrap(), router).unwrap();
+
+        // the weth deposit is emitted within the second subcall, the precompile call has no vm trace of its own
+        let (logs, _) = call_tree.flatten();
+        let logs: Vec<(Address, Vec<H256>, U256)> = logs
+            .iter()
+            .map(|log| (log.address, log.topics.clone(), U256::from(&log.data[..])))
+            .collect();
+        assert_eq!(
+            logs,
+            vec![
+                (weth, vec![deposit, H256::from(router)], U256::exp10(18)),
+                (router, vec![H256::repeat_byte(0xab)], U256::from(42)),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_traces_out_of_order() {
+        let block_trace: BlockTrace =
+            serde_json::from_str(include_str!("fixtures/parity_trace_call.json")).unwrap();
+        let mut traces = block_trace.trace.unwrap();
+        traces.swap(1, 2);
+
+        assert!(call_tree(traces).is_err());
+    }
+}
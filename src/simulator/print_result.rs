@@ -1,28 +1,346 @@
-use super::types::SimulationResults;
-use ethers::utils::format_units;
+use ethers::{
+    types::{Bytes, H256, I256, U256},
+    utils::{format_units, hex},
+};
+use serde::Serialize;
 
-pub fn print_result(simulated_infos: Vec<SimulationResults>) {
-    if simulated_infos.len() == 0 {
+use super::error::{Result, SimulationError};
+use super::types::{
+    AssetChanges, BalanceCheck, BatchReport, BatchSummary, CallFrame, CallKind, CustomEvent,
+    FrameLog, NftAmount, RawLog, Sale, SimulationReport, SimulationResults, SkippedBalanceChecks,
+    Swap, TokenInfo,
+};
+
+// Bump whenever a field of the json output is renamed, removed or changes meaning. Adding fields is not a breaking
+// change and keeps the version.
+// 2: transfers from or to nowhere are reported as `mint` and `burn` rather than `transfer`
+pub const JSON_SCHEMA_VERSION: u32 = 2;
+
+#[derive(Serialize)]
+struct JsonOutput<'a> {
+    schema_version: u32,
+    #[serde(flatten)]
+    report: JsonReport<'a>,
+}
+
+#[derive(Serialize)]
+struct JsonBatchOutput<'a> {
+    schema_version: u32,
+    transactions: Vec<JsonReport<'a>>,
+    summary: &'a BatchSummary,
+}
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    success: bool,
+    gas_used: String,
+    effective_gas_price: Option<String>,
+    block_number: Option<u64>,
+    tx_hash: Option<H256>,
+    revert_data: Option<&'a Bytes>,
+    revert_reason: Option<String>,
+    rejection: Option<&'a str>,
+    results: Vec<JsonResult<'a>>,
+    logs: &'a [RawLog],
+    call_tree: Option<&'a CallFrame>,
+    custom_events: &'a [CustomEvent],
+    swaps: &'a [Swap],
+    sales: &'a [Sale],
+    undecoded_logs: &'a [usize],
+    asset_changes: &'a [AssetChanges],
+    balance_checks: Option<&'a [BalanceCheck]>,
+    balance_checks_skipped: Option<SkippedBalanceChecks>,
+}
+
+#[derive(Serialize)]
+struct JsonResult<'a> {
+    #[serde(flatten)]
+    result: &'a SimulationResults,
+    formatted_amount: String,
+}
+
+// prints the report as a single json document, see the readme for the schema
+pub fn print_json(report: &SimulationReport) -> Result<()> {
+    print_serialized(&JsonOutput {
+        schema_version: JSON_SCHEMA_VERSION,
+        report: json_report(report),
+    })
+}
+
+// prints a batch as a single json document, every transaction follows the single report schema
+pub fn print_batch_json(batch: &BatchReport) -> Result<()> {
+    print_serialized(&JsonBatchOutput {
+        schema_version: JSON_SCHEMA_VERSION,
+        transactions: batch.reports.iter().map(json_report).collect(),
+        summary: &batch.summary,
+    })
+}
+
+pub fn print_batch_result(batch: BatchReport) {
+    let summary = batch.summary;
+
+    for (index, report) in batch.reports.into_iter().enumerate() {
+        println!(
+            "\n\n\x1b[1m\x1b[94m ===================================================== TRANSACTION {} =====================================================\x1b[0m",
+            index + 1
+        );
+        print_result(report);
+    }
+
+    println!(
+        "\n\x1b[1m Batch Summary:
+    \x1b[92m Transactions: \x1b[0m {}
+    \x1b[92m Succeeded: \x1b[0m {}
+    \x1b[92m Reverted: \x1b[0m {}
+    \x1b[92m Total Gas Used: \x1b[0m {}
+    \x1b[92m Watched Events: \x1b[0m {}
+    \x1b[92m Mints: \x1b[0m {}
+    \x1b[92m Burns: \x1b[0m {}\n",
+        summary.transactions,
+        summary.succeeded,
+        summary.reverted,
+        summary.total_gas_used,
+        summary.total_results,
+        summary.mints,
+        summary.burns
+    );
+}
+
+fn print_serialized<T: Serialize>(output: &T) -> Result<()> {
+    let json = serde_json::to_string_pretty(output)
+        .map_err(|e| SimulationError::Decode(format!("could not serialize report: {}", e)))?;
+    println!("{}", json);
+
+    Ok(())
+}
+
+fn json_report(report: &SimulationReport) -> JsonReport<'_> {
+    JsonReport {
+        success: report.success,
+        gas_used: report.gas_used.to_string(),
+        effective_gas_price: report.effective_gas_price.map(|p| p.to_string()),
+        block_number: report.block_number,
+        tx_hash: report.tx_hash,
+        revert_data: report.revert_data.as_ref(),
+        revert_reason: report.revert_reason.as_ref().map(|r| r.to_string()),
+        rejection: report.rejection.as_deref(),
+        results: report
+            .results
+            .iter()
+            .map(|result| JsonResult {
+                result,
+                formatted_amount: format_amount(result),
+            })
+            .collect(),
+        logs: &report.logs,
+        call_tree: report.call_tree.as_ref(),
+        custom_events: &report.custom_events,
+        swaps: &report.swaps,
+        sales: &report.sales,
+        undecoded_logs: &report.undecoded_logs,
+        asset_changes: &report.asset_changes,
+        balance_checks: report.balance_checks.as_deref(),
+        balance_checks_skipped: report.balance_checks_skipped,
+    }
+}
+
+// amount scaled down by the token's decimals, tokens reporting nonsensical decimals just get their raw amount
+fn format_amount(simulated_info: &SimulationResults) -> String {
+    format_token_amount(simulated_info.amount, &simulated_info.token_info)
+}
+
+fn format_token_amount(amount: U256, token_info: &TokenInfo) -> String {
+    let decimals: u32 = token_info.decimals.to_string().parse().unwrap_or_default();
+    match decimals > 0 {
+        true => format_units(amount, decimals).unwrap_or_else(|_| format!("{}", amount)),
+        false => format!("{}", amount),
+    }
+}
+
+pub fn print_result(report: SimulationReport) {
+    let status = match report.success {
+        true => "\x1b[92mSuccess\x1b[0m",
+        false => "\x1b[91mReverted\x1b[0m",
+    };
+    println!(
+        "\n\x1b[1m Execution:
+    \x1b[92m Status: \x1b[0m {}
+    \x1b[92m Gas Used: \x1b[0m {}
+    \x1b[92m Block Number: \x1b[0m {}
+    \x1b[92m Tx Hash: \x1b[0m {}
+    \x1b[92m Logs Emitted: \x1b[0m {}",
+        status,
+        report.gas_used,
+        report
+            .block_number
+            .map(|n| n.to_string())
+            .unwrap_or_default(),
+        report
+            .tx_hash
+            .map(|h| format!("{:?}", h))
+            .unwrap_or_default(),
+        report.logs.len()
+    );
+    if let Some(revert_data) = &report.revert_data {
+        println!("    \x1b[92m Revert Data: \x1b[0m {}", revert_data);
+    }
+    if let Some(revert_reason) = &report.revert_reason {
+        println!("    \x1b[91m Revert Reason: \x1b[0m {}", revert_reason);
+    }
+    if let Some(rejection) = &report.rejection {
+        println!("    \x1b[91m Rejected: \x1b[0m {}", rejection);
+    }
+    if !report.asset_changes.is_empty() {
+        println!("\n\x1b[1m Asset Changes:\x1b[0m");
+        for changes in report.asset_changes.iter() {
+            for line in asset_change_lines(changes) {
+                println!("{}", line);
+            }
+        }
+    }
+    if let Some(balance_checks) = &report.balance_checks {
+        let mismatches: Vec<&BalanceCheck> = balance_checks.iter().filter(|c| c.mismatch).collect();
+        println!(
+            "\n\x1b[1m Balance Checks:\x1b[0m\n  {} balances read, {} not matching the events",
+            balance_checks.len(),
+            mismatches.len()
+        );
+        for check in mismatches {
+            println!("{}", balance_mismatch_line(check));
+        }
+    }
+    // not being mined is the norm outside the anvil backend, only a mined transaction's skipped checks are worth
+    // pointing out
+    let skipped = match report.balance_checks_skipped {
+        Some(SkippedBalanceChecks::SharedBlock) => Some("mined along with other transactions"),
+        Some(SkippedBalanceChecks::ReadsFailed) => Some("the balances couldn't be read"),
+        _ => None,
+    };
+    if let Some(reason) = skipped {
+        println!("\n\x1b[1m Balance Checks:\x1b[0m\n  skipped, {}", reason);
+    }
+    if let Some(call_tree) = &report.call_tree {
+        println!("\n\x1b[1m Call Tree:\x1b[0m");
+        let mut lines = Vec::new();
+        call_tree_lines(call_tree, 1, &mut lines);
+        for line in lines {
+            println!("{}", line);
+        }
+    }
+
+    if !report.custom_events.is_empty() {
+        println!("\n\x1b[1m Custom Events:\x1b[0m");
+        for (index, event) in report.custom_events.iter().enumerate() {
+            println!(
+                "  \x1b[94m{}. \x1b[0m{} ({:?})",
+                index + 1,
+                event.name,
+                event.address
+            );
+            for field in event.fields.iter() {
+                println!("        {} ({}): {}", field.name, field.kind, field.value);
+            }
+        }
+    }
+
+    if !report.swaps.is_empty() {
+        println!("\n\x1b[1m Swaps:\x1b[0m");
+        for (index, swap) in report.swaps.iter().enumerate() {
+            println!(
+                "  \x1b[94m{}. \x1b[0m{:?} ({:?})
+        {} {} -> {} {}",
+                index + 1,
+                swap.protocol,
+                swap.pool,
+                format_token_amount(swap.amount_in, &swap.token_in),
+                swap.token_in.symbol,
+                format_token_amount(swap.amount_out, &swap.token_out),
+                swap.token_out.symbol
+            );
+        }
+    }
+
+    if !report.sales.is_empty() {
+        println!("\n\x1b[1m Sales:\x1b[0m");
+        for (index, sale) in report.sales.iter().enumerate() {
+            println!(
+                "  \x1b[94m{}. \x1b[0m{:?} sold to {:?} ({:?})",
+                index + 1,
+                sale.seller,
+                sale.buyer,
+                sale.marketplace
+            );
+            for item in sale.offer.iter().chain(sale.consideration.iter()) {
+                let id = item.id.map(|id| format!(" #{}", id)).unwrap_or_default();
+                println!(
+                    "        {} {}{} -> {:?}",
+                    format_token_amount(item.amount, &item.token_info),
+                    item.token_info.symbol,
+                    id,
+                    item.recipient.unwrap_or(sale.recipient)
+                );
+            }
+            if let Some(price) = &sale.price {
+                println!(
+                    "        Price: {} {} ({} {} per item)",
+                    format_token_amount(price.total, &price.token_info),
+                    price.token_info.symbol,
+                    format_token_amount(price.per_item, &price.token_info),
+                    price.token_info.symbol
+                );
+            }
+            for fee in sale.fees.iter() {
+                println!(
+                    "        {:?} fee: {} {} to {:?}",
+                    fee.kind,
+                    format_token_amount(fee.amount, &fee.token_info),
+                    fee.token_info.symbol,
+                    fee.recipient
+                );
+            }
+        }
+    }
+
+    // no decoder knew what to make of these, shown raw
+    let undecoded: Vec<&RawLog> = report
+        .logs
+        .iter()
+        .filter(|log| report.undecoded_logs.contains(&log.log_index))
+        .collect();
+    if !undecoded.is_empty() {
+        println!("\n\x1b[1m Undecoded Logs:\x1b[0m");
+        for log in undecoded {
+            println!("  \x1b[94m{}. \x1b[0m{:?}", log.log_index, log.address);
+            for (index, topic) in log.topics.iter().enumerate() {
+                println!("        topic{}: {:?}", index, topic);
+            }
+            println!("        data: {}", log.data);
+        }
+    }
+
+    let simulated_infos = report.results;
+    if simulated_infos.is_empty() {
         println!("No watched events detected!");
         return;
     }
 
     println!("\n\n\n\n\x1b[92m _____________________________________________________________________ SIMULATION RESULTS _____________________________________________________________________\n");
     for (index, simulated_info) in simulated_infos.iter().enumerate() {
-        let decimals: u32 = simulated_info
-            .token_info
-            .decimals
-            .to_string()
-            .parse()
-            .expect("failed to convert to u32");
-        let amount = match decimals > 0 {
-            true => format_units(simulated_info.amount, decimals).unwrap(),
-            false => format!("{}", simulated_info.amount),
-        };
+        let amount = format_amount(simulated_info);
         let id = match simulated_info.id {
             Some(id) => format!("{}", id),
             None => "".to_owned(),
         };
+        // wraps and vault operations also tell what the token was exchanged for
+        let underlying = match &simulated_info.underlying {
+            Some(underlying) => format!(
+                ",\n                Underlying: {} {} ({:?})",
+                format_token_amount(underlying.amount, &underlying.token_info),
+                underlying.token_info.symbol,
+                underlying.token_info.address
+            ),
+            None => "".to_owned(),
+        };
 
         println!(
             "  \x1b[94m{}. \x1b[0m{:?}
@@ -37,7 +355,7 @@ pub fn print_result(simulated_infos: Vec<SimulationResults>) {
                 From: {:?},
                 To: {:?},
                 id: {:?},
-                Amount: {:?}
+                Amount: {:?}{}
 \n\x1b[92m________________________________________________________________________________________________________________________________________________________________\n",
             index + 1,
             simulated_info.operation,
@@ -49,7 +367,235 @@ pub fn print_result(simulated_infos: Vec<SimulationResults>) {
             simulated_info.from,
             simulated_info.to,
             id,
-            amount
+            amount,
+            underlying
         );
     }
 }
+
+// the address, then one line per token it gained or lost
+fn asset_change_lines(changes: &AssetChanges) -> Vec<String> {
+    let mut lines = vec![format!(
+        "  {:?}{}",
+        changes.address,
+        match changes.sender {
+            true => " \x1b[94m(sender)\x1b[0m",
+            false => "",
+        }
+    )];
+
+    for token in changes.tokens.iter() {
+        let (sign, color) = match token.delta.is_negative() {
+            true => ("-", "\x1b[91m"),
+            false => ("+", "\x1b[92m"),
+        };
+        let ids = |nfts: &[NftAmount], verb: &str| match nfts.is_empty() {
+            true => "".to_owned(),
+            false => format!(
+                ", {} {}",
+                verb,
+                nfts.iter()
+                    .map(|nft| format!("#{} x{}", nft.id, nft.amount))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+        };
+        lines.push(format!(
+            "        {}{}{}\x1b[0m {} ({:?}){}{}",
+            color,
+            sign,
+            format_token_amount(token.delta.unsigned_abs(), &token.token_info),
+            token.token_info.symbol,
+            token.token_info.address,
+            ids(&token.gained, "gained"),
+            ids(&token.lost, "lost")
+        ));
+    }
+    lines
+}
+
+// the real and the event-derived change of a balance that disagree
+fn balance_mismatch_line(check: &BalanceCheck) -> String {
+    let signed = |delta: &I256| {
+        format!(
+            "{}{}",
+            match delta.is_negative() {
+                true => "-",
+                false => "+",
+            },
+            format_token_amount(delta.unsigned_abs(), &check.token_info)
+        )
+    };
+    let id = check.id.map(|id| format!(" #{}", id)).unwrap_or_default();
+
+    format!(
+        "        \x1b[91m{:?}\x1b[0m {}{} ({:?}): {} on chain, {} from events",
+        check.address,
+        check.token_info.symbol,
+        id,
+        check.token_info.address,
+        signed(&check.delta),
+        signed(&check.expected_delta)
+    )
+}
+
+// One line per frame, children and the frame's own logs indented below it in the order they happened
+fn call_tree_lines(frame: &CallFrame, depth: usize, lines: &mut Vec<String>) {
+    let indent = "    ".repeat(depth);
+    let status = match frame.reverted {
+        true => "\x1b[91mreverted\x1b[0m",
+        false => "\x1b[92mok\x1b[0m",
+    };
+    // creations have init code as input, not calldata
+    let input = match frame.kind {
+        CallKind::Create | CallKind::Create2 => format!(" init code: {} bytes", frame.input.len()),
+        _ => match frame.input.get(..4) {
+            Some(selector) => format!(" selector: 0x{}", hex::encode(selector)),
+            None => "".to_owned(),
+        },
+    };
+    lines.push(format!(
+        "{}{} {:?} -> {:?}{} value: {} gas: {} {}",
+        indent, frame.kind, frame.from, frame.to, input, frame.value, frame.gas_used, status
+    ));
+
+    let mut logs = frame.logs.iter().peekable();
+    for (position, call) in frame.calls.iter().enumerate() {
+        while let Some(log) = logs.next_if(|log| log.position <= position) {
+            lines.push(log_line(&indent, log));
+        }
+        call_tree_lines(call, depth + 1, lines);
+    }
+    for log in logs {
+        lines.push(log_line(&indent, log));
+    }
+}
+
+// a log is shown with its first topic, the event signature for any solidity event that isn't anonymous
+fn log_line(indent: &str, log: &FrameLog) -> String {
+    let topic = log
+        .topics
+        .first()
+        .map(|topic| format!(" {:?}", topic))
+        .unwrap_or_default();
+    format!(
+        "{}    LOG{} {:?}{}",
+        indent,
+        log.topics.len(),
+        log.address,
+        topic
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::simulator::types::{Operation, Standard};
+    use ethers::types::Address;
+    use serde_json::json;
+
+    #[test]
+    fn json_result_matches_schema() {
+        let result = SimulationResults {
+            operation: Operation::Transfer,
+            token_info: TokenInfo {
+                standard: Standard::Eip20,
+                address: Address::repeat_byte(1),
+                name: "Wrapped Ether".to_owned(),
+                symbol: "WETH".to_owned(),
+                decimals: U256::from(18),
+            },
+            from: Address::repeat_byte(2),
+            to: Address::repeat_byte(3),
+            id: None,
+            amount: U256::from_dec_str("1500000000000000").unwrap(),
+            batch_log_index: None,
+            underlying: None,
+        };
+        let json_result = JsonResult {
+            result: &result,
+            formatted_amount: format_amount(&result),
+        };
+
+        assert_eq!(
+            serde_json::to_value(json_result).unwrap(),
+            json!({
+                "operation": "transfer",
+                "token_info": {
+                    "standard": "eip20",
+                    "address": "0x0101010101010101010101010101010101010101",
+                    "name": "Wrapped Ether",
+                    "symbol": "WETH",
+                    "decimals": "18"
+                },
+                "from": "0x0202020202020202020202020202020202020202",
+                "to": "0x0303030303030303030303030303030303030303",
+                "id": null,
+                "amount": "1500000000000000",
+                "batch_log_index": null,
+                "underlying": null,
+                "formatted_amount": "0.001500000000000000"
+            })
+        );
+    }
+
+    #[test]
+    fn call_tree_is_indented_in_execution_order() {
+        let (user, router, token) = (
+            Address::repeat_byte(0x11),
+            Address::repeat_byte(0x77),
+            Address::repeat_byte(0xcc),
+        );
+        let transfer_log = FrameLog {
+            address: token,
+            topics: vec![H256::repeat_byte(0xdd), H256::from(router)],
+            data: Bytes::new(),
+            position: 0,
+        };
+        let call_tree = CallFrame {
+            kind: CallKind::Call,
+            from: user,
+            to: router,
+            value: U256::from(7),
+            gas_used: U256::from(50_000),
+            input: "0x7ff36ab5aabb".parse().unwrap(),
+            output: Bytes::new(),
+            reverted: false,
+            calls: vec![CallFrame {
+                kind: CallKind::DelegateCall,
+                from: router,
+                to: token,
+                value: U256::zero(),
+                gas_used: U256::from(2_000),
+                input: Bytes::new(),
+                output: Bytes::new(),
+                reverted: true,
+                calls: Vec::new(),
+                logs: Vec::new(),
+            }],
+            logs: vec![transfer_log],
+        };
+
+        let mut lines = Vec::new();
+        call_tree_lines(&call_tree, 0, &mut lines);
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with(&format!(
+            "CALL {:?} -> {:?} selector: 0x7ff36ab5 value: 7 gas: 50000",
+            user, router
+        )));
+        // the log was emitted before the subcall
+        assert_eq!(
+            lines[1],
+            format!("    LOG2 {:?} {:?}", token, H256::repeat_byte(0xdd))
+        );
+        assert!(lines[2].starts_with("    DELEGATECALL"));
+        assert!(lines[2].contains("reverted"));
+
+        let json = serde_json::to_value(&call_tree).unwrap();
+        assert_eq!(json["kind"], "call");
+        assert_eq!(json["value"], "7");
+        assert_eq!(json["calls"][0]["kind"], "delegate_call");
+        assert_eq!(json["logs"][0]["position"], 0);
+    }
+}
@@ -1,92 +1,306 @@
+use async_trait::async_trait;
 use ethers::{
-    abi::{decode_whole, ParamType, Token},
+    abi::{decode, decode_whole, ParamType, Token},
     contract::Multicall,
     prelude::abigen,
     providers::{Http, Provider},
-    types::{Address, U256},
+    types::{Address, BlockNumber, H256, U256},
 };
-use eyre::Result;
-use std::process;
+use std::collections::HashMap;
 use std::sync::Arc;
 
-use super::constants::{APPROVAL, APPROVAL_FOR_ALL, CHECKED_TOPICS, TRANSFER, TRANSFER_SINGLE};
-use super::types::{MyLog, Operation, SimulationResults, Standard, TokenInfo};
+use super::constants::{
+    APPROVAL, APPROVAL_FOR_ALL, DEAD_ADDRESS, ERC1155_INTERFACE_ID, ERC721_INTERFACE_ID, TRANSFER,
+    TRANSFER_BATCH, TRANSFER_SINGLE,
+};
+use super::decoder::{DecodeContext, DecodedEvent, EventDecoder};
+use super::error::{Result, SimulationError};
+use super::types::{Operation, RawLog, SimulationResults, Standard, TokenInfo};
 
-pub async fn process_logs(
-    log: MyLog,
-    provider: Provider<Http>,
-) -> Result<Option<SimulationResults>> {
-    let topic0 = log.topics[0]
-        .as_bytes()
-        .try_into()
-        .expect("could not convert topic0 into a uint8 array");
-
-    if CHECKED_TOPICS.contains(&topic0) {
-        let amount: U256;
-        let id: Option<U256>;
-        let standard: Standard;
-
-        if log.data.len() == 64 {
-            let decoded =
-                match decode_whole(&[ParamType::Uint(256), ParamType::Uint(256)], &log.data) {
-                    Ok(x) => x,
-                    Err(err) => {
-                        eprintln!("decoding failed with err: {}", err);
-                        process::exit(1);
-                    }
-                };
-            (id, amount) = match (&decoded[0], &decoded[1]) {
-                (Token::Uint(x), Token::Uint(y)) => (Some(*x), *y),
-                _ => {
-                    eprintln!("Wrong type decoded");
-                    process::exit(1);
-                }
-            };
-            standard = Standard::Eip1155;
-        } else if log.data.len() == 32 {
-            let decoded = match decode_whole(&[ParamType::Uint(256)], &log.data) {
-                Ok(x) => x,
-                Err(err) => {
-                    eprintln!("decoding failed with err: {}", err);
-                    process::exit(1);
-                }
-            };
-            amount = match decoded[0] {
-                Token::Uint(x) => x,
-                _ => {
-                    eprintln!("Wrong type decoded");
-                    process::exit(1);
-                }
-            };
-            id = None;
+// ERC20 Transfer and Approval, with the amount as their only data
+pub struct Erc20Decoder;
+
+// ERC721 Transfer and Approval, with the token id indexed, and ApprovalForAll of ERC721 contracts. An ApprovalForAll
+// of a contract that doesn't say what it is lands here too, with `Standard::None`.
+pub struct Erc721Decoder;
+
+// ERC1155 TransferSingle, TransferBatch and ApprovalForAll
+pub struct Erc1155Decoder;
+
+#[async_trait]
+impl EventDecoder for Erc20Decoder {
+    fn topics(&self) -> Vec<H256> {
+        vec![H256::from(TRANSFER), H256::from(APPROVAL)]
+    }
+
+    async fn decode(
+        &self,
+        log: &RawLog,
+        context: &mut DecodeContext<'_>,
+    ) -> Result<Vec<DecodedEvent>> {
+        match standard_of(log, context).await? {
+            Standard::Eip20 => token_event(log, context, Standard::Eip20).await,
+            _ => Ok(Vec::new()),
+        }
+    }
+}
 
-            standard = match &topic0 {
-                &APPROVAL => Standard::Eip20,
-                &TRANSFER => Standard::Eip20,
-                &APPROVAL_FOR_ALL => Standard::Eip721,
-                _ => Standard::None,
+#[async_trait]
+impl EventDecoder for Erc721Decoder {
+    fn topics(&self) -> Vec<H256> {
+        vec![
+            H256::from(TRANSFER),
+            H256::from(APPROVAL),
+            H256::from(APPROVAL_FOR_ALL),
+        ]
+    }
+
+    async fn decode(
+        &self,
+        log: &RawLog,
+        context: &mut DecodeContext<'_>,
+    ) -> Result<Vec<DecodedEvent>> {
+        // as long as it indexes the owner and the operator
+        let is_approval_for_all =
+            log.topics[0] == H256::from(APPROVAL_FOR_ALL) && log.topics.len() == 3;
+        match standard_of(log, context).await? {
+            Standard::Eip721 => token_event(log, context, Standard::Eip721).await,
+            Standard::None if is_approval_for_all => {
+                token_event(log, context, Standard::None).await
             }
-        } else {
-            amount = U256::from(1);
+            _ => Ok(Vec::new()),
+        }
+    }
+}
 
-            let d: &[u8] = log.topics[3]
-                .as_bytes()
-                .try_into()
-                .expect("could not convert topic4 into a uint8 array");
-            id = Some(U256::from(d));
+#[async_trait]
+impl EventDecoder for Erc1155Decoder {
+    fn topics(&self) -> Vec<H256> {
+        vec![
+            H256::from(TRANSFER_SINGLE),
+            H256::from(TRANSFER_BATCH),
+            H256::from(APPROVAL_FOR_ALL),
+        ]
+    }
 
-            standard = Standard::Eip721;
+    async fn decode(
+        &self,
+        log: &RawLog,
+        context: &mut DecodeContext<'_>,
+    ) -> Result<Vec<DecodedEvent>> {
+        if log.topics[0] == H256::from(TRANSFER_BATCH) {
+            return transfer_batch(log, context).await;
+        }
+        match standard_of(log, context).await? {
+            Standard::Eip1155 => token_event(log, context, Standard::Eip1155).await,
+            _ => Ok(Vec::new()),
+        }
+    }
+}
+
+async fn standard_of(log: &RawLog, context: &mut DecodeContext<'_>) -> Result<Standard> {
+    match standard_from_shape(log.topics[0].to_fixed_bytes(), log) {
+        Some(standard) => Ok(standard),
+        None => {
+            context
+                .interfaces
+                .nft_standard(log.address, context.provider, context.block)
+                .await
+        }
+    }
+}
+
+// a single watched event of a contract of the given standard
+async fn token_event(
+    log: &RawLog,
+    context: &DecodeContext<'_>,
+    standard: Standard,
+) -> Result<Vec<DecodedEvent>> {
+    let topic0 = log.topics[0].to_fixed_bytes();
+
+    // data that doesn't decode as the standard's isn't its event, the next decoder or the abis might know it
+    let (id, amount) = match (topic0, &standard) {
+        (TRANSFER_SINGLE, _) => {
+            match decode_whole(&[ParamType::Uint(256), ParamType::Uint(256)], &log.data).as_deref()
+            {
+                Ok([Token::Uint(id), Token::Uint(amount)]) => (Some(*id), *amount),
+                _ => return Ok(Vec::new()),
+            }
+        }
+        // the approved flag, 1 for an approval and 0 for a revocation
+        (APPROVAL_FOR_ALL, _) => match decode_whole(&[ParamType::Bool], &log.data).as_deref() {
+            Ok([Token::Bool(approved)]) => (None, U256::from(*approved as u8)),
+            _ => return Ok(Vec::new()),
+        },
+        (_, Standard::Eip20) => match decode_whole(&[ParamType::Uint(256)], &log.data).as_deref() {
+            Ok([Token::Uint(amount)]) => (None, *amount),
+            _ => return Ok(Vec::new()),
+        },
+        (_, Standard::Eip721) => (Some(U256::from(log.topics[3].as_bytes())), U256::from(1)),
+        // a Transfer or Approval shaped like neither standard's
+        _ => return Ok(Vec::new()),
+    };
+
+    let (name, symbol, decimals) = get_token_name_and_symbol(
+        log.address,
+        context.provider.clone(),
+        context.block,
+        &standard,
+    )
+    .await?;
+
+    let result =
+        match_simulation_result(topic0, name, symbol, decimals, amount, id, log, standard)?;
+    Ok(result.into_iter().map(DecodedEvent::Token).collect())
+}
+
+// What the event's shape tells about the contract's standard, `None` when only the contract itself can tell.
+// ERC721 indexes the token id where ERC20 has its amount as the only data, ApprovalForAll is shared by ERC721 and
+// ERC1155. Events missing the addresses the standards index are left alone.
+fn standard_from_shape(topic0: [u8; 32], log: &RawLog) -> Option<Standard> {
+    match topic0 {
+        TRANSFER_SINGLE | TRANSFER_BATCH => match log.topics.len() {
+            4 => Some(Standard::Eip1155),
+            _ => Some(Standard::None),
+        },
+        APPROVAL_FOR_ALL if log.topics.len() != 3 => Some(Standard::None),
+        TRANSFER | APPROVAL => match (log.topics.len(), log.data.len()) {
+            (4, 0) => Some(Standard::Eip721),
+            (3, 32) => Some(Standard::Eip20),
+            _ => Some(Standard::None),
+        },
+        _ => None,
+    }
+}
+
+// ERC-165 answers of the contracts seen so far, so every contract is only probed once
+#[derive(Default)]
+pub struct InterfaceCache {
+    standards: HashMap<Address, Standard>,
+}
+
+impl InterfaceCache {
+    // the nft standard the contract claims through ERC-165, `Standard::None` if it claims neither
+    async fn nft_standard(
+        &mut self,
+        address: Address,
+        provider: &Provider<Http>,
+        block: BlockNumber,
+    ) -> Result<Standard> {
+        if let Some(standard) = self.standards.get(&address) {
+            return Ok(*standard);
+        }
+
+        abigen!(
+            InterfaceInstance,
+            r#"[
+                function supportsInterface(bytes4 interfaceId) external view returns (bool)
+            ]"#,
+        );
+
+        let client = Arc::new(provider.clone());
+        let instance = InterfaceInstance::new(address, client.clone());
+        let supports_721 = instance.supports_interface(ERC721_INTERFACE_ID);
+        let supports_1155 = instance.supports_interface(ERC1155_INTERFACE_ID);
+
+        let mut multicall = Multicall::new(client, None)
+            .await
+            .map_err(|e| SimulationError::Rpc(e.to_string()))?
+            .block(block);
+        multicall
+            .add_call(supports_721, true)
+            .add_call(supports_1155, true);
+        // contracts without ERC-165 revert or return garbage, which counts as a no
+        let supported: Vec<bool> = multicall
+            .call_raw()
+            .await
+            .map_err(|e| SimulationError::Rpc(e.to_string()))?
+            .into_iter()
+            .map(|result| matches!(result, Ok(Token::Bool(true))))
+            .collect();
+
+        let standard = match supported.as_slice() {
+            [_, true] => Standard::Eip1155,
+            [true, _] => Standard::Eip721,
+            _ => Standard::None,
         };
+        self.standards.insert(address, standard);
 
-        let (name, symbol, decimals) =
-            get_token_name_and_symbol(log.address, provider, &standard).await?;
+        Ok(standard)
+    }
+}
 
-        match_simulation_result(topic0, name, symbol, decimals, amount, id, log, standard)
-    } else {
-        Ok(None)
+// one result per transferred id
+async fn transfer_batch(log: &RawLog, context: &DecodeContext<'_>) -> Result<Vec<DecodedEvent>> {
+    // not the standard's TransferBatch without indexed operator, from and to
+    let (from, to) = match log.topics.as_slice() {
+        [_, _, from, to] => (Address::from(*from), Address::from(*to)),
+        _ => return Ok(Vec::new()),
+    };
+    // data laid out some other way is left to the other decoders
+    let transfers = decode_transfer_batch(log);
+    if transfers.is_empty() {
+        return Ok(Vec::new());
     }
+
+    let (name, symbol, decimals) = get_token_name_and_symbol(
+        log.address,
+        context.provider.clone(),
+        context.block,
+        &Standard::Eip1155,
+    )
+    .await?;
+
+    Ok(transfers
+        .into_iter()
+        .map(|(id, amount)| {
+            DecodedEvent::Token(SimulationResults {
+                operation: Operation::TransferBatch,
+                token_info: TokenInfo {
+                    standard: Standard::Eip1155,
+                    address: log.address,
+                    name: name.clone(),
+                    symbol: symbol.clone(),
+                    decimals,
+                },
+                from,
+                to,
+                id: Some(id),
+                amount,
+                batch_log_index: Some(log.log_index),
+                underlying: None,
+            })
+        })
+        .collect())
 }
 
+// the (id, amount) pairs of a TransferBatch's `uint256[] ids, uint256[] values` data, none when the data isn't laid
+// out that way or pairs a different number of ids and values
+fn decode_transfer_batch(log: &RawLog) -> Vec<(U256, U256)> {
+    let array = || ParamType::Array(Box::new(ParamType::Uint(256)));
+    let uints = |token: Token| -> Option<Vec<U256>> {
+        token
+            .into_array()?
+            .into_iter()
+            .map(Token::into_uint)
+            .collect()
+    };
+
+    let mut decoded = decode(&[array(), array()], &log.data)
+        .unwrap_or_default()
+        .into_iter();
+    match (
+        decoded.next().and_then(uints),
+        decoded.next().and_then(uints),
+    ) {
+        (Some(ids), Some(amounts)) if ids.len() == amounts.len() => {
+            ids.into_iter().zip(amounts).collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn match_simulation_result(
     topic0: [u8; 32],
     name: String,
@@ -94,7 +308,7 @@ fn match_simulation_result(
     decimals: U256,
     amount: U256,
     id: Option<U256>,
-    log: MyLog,
+    log: &RawLog,
     standard: Standard,
 ) -> Result<Option<SimulationResults>> {
     match topic0 {
@@ -111,6 +325,8 @@ fn match_simulation_result(
             to: Address::from(log.topics[2]),
             amount,
             id,
+            batch_log_index: None,
+            underlying: None,
         })),
         TRANSFER => Ok(Some(SimulationResults {
             operation: Operation::Transfer,
@@ -125,6 +341,8 @@ fn match_simulation_result(
             to: Address::from(log.topics[2]),
             amount,
             id,
+            batch_log_index: None,
+            underlying: None,
         })),
         APPROVAL_FOR_ALL => Ok(Some(SimulationResults {
             operation: Operation::ApprovalForAll,
@@ -139,6 +357,8 @@ fn match_simulation_result(
             to: Address::from(log.topics[2]),
             amount,
             id,
+            batch_log_index: None,
+            underlying: None,
         })),
         TRANSFER_SINGLE => Ok(Some(SimulationResults {
             operation: Operation::TransferSingle,
@@ -153,27 +373,68 @@ fn match_simulation_result(
             to: Address::from(log.topics[3]),
             amount,
             id,
+            batch_log_index: None,
+            underlying: None,
         })),
-        _ => Ok(Some(SimulationResults {
-            operation: Operation::TransferBatch,
-            token_info: TokenInfo {
-                standard,
-                name,
-                symbol,
-                decimals,
-                address: log.address,
-            },
-            from: Address::from(log.topics[2]),
-            to: Address::from(log.topics[3]),
-            amount,
-            id,
-        })),
+        // TransferBatch expands into several results, see `transfer_batch`
+        _ => Ok(None),
+    }
+}
+
+// Relabels token transfers creating or destroying supply: the ones from the zero address are mints, the ones to the
+// zero address, the dead address or any of `burn_addresses` are burns. Native ETH has no supply to change.
+pub fn classify_supply_changes(results: &mut [SimulationResults], burn_addresses: &[Address]) {
+    let is_burn_address = |address: &Address| {
+        address.is_zero()
+            || *address == Address::from(DEAD_ADDRESS)
+            || burn_addresses.contains(address)
+    };
+
+    for result in results.iter_mut() {
+        let is_transfer = matches!(
+            result.operation,
+            Operation::Transfer | Operation::TransferSingle | Operation::TransferBatch
+        );
+        if !is_transfer || result.token_info.standard == Standard::Native {
+            continue;
+        }
+
+        if result.from.is_zero() {
+            result.operation = Operation::Mint;
+        } else if is_burn_address(&result.to) {
+            result.operation = Operation::Burn;
+        }
+    }
+}
+
+// a transfer of the chain's native currency, sent along with a call
+pub fn native_transfer(from: Address, to: Address, amount: U256) -> SimulationResults {
+    SimulationResults {
+        operation: Operation::Transfer,
+        token_info: native_token(),
+        from,
+        to,
+        id: None,
+        amount,
+        batch_log_index: None,
+        underlying: None,
     }
 }
 
-async fn get_token_name_and_symbol(
+pub fn native_token() -> TokenInfo {
+    TokenInfo {
+        standard: Standard::Native,
+        address: Address::zero(),
+        name: "Ether".to_owned(),
+        symbol: "ETH".to_owned(),
+        decimals: U256::from(18),
+    }
+}
+
+pub async fn get_token_name_and_symbol(
     address: Address,
     provider: Provider<Http>,
+    block: BlockNumber,
     standard: &Standard,
 ) -> Result<(String, String, U256)> {
     abigen!(
@@ -192,13 +453,16 @@ async fn get_token_name_and_symbol(
     let symbol_fn = token_instance.method::<_, String>("symbol", ())?;
     let decimals_fn = token_instance.method::<_, U256>("decimals", ())?;
 
-    let mut multicall = Multicall::new(client, None).await?;
+    let mut multicall = Multicall::new(client, None)
+        .await
+        .map_err(|e| SimulationError::Rpc(e.to_string()))?
+        .block(block);
 
     let name: String;
     let symbol: String;
     let decimals: U256;
     match standard {
-        Standard::Eip20 => {
+        Standard::Eip20 | Standard::Eip4626 => {
             multicall
                 .add_call(name_fn, true)
                 .add_call(symbol_fn, true)
@@ -206,7 +470,7 @@ async fn get_token_name_and_symbol(
             // `await`ing on the `call` method lets us fetch the return values of both the above calls in one single RPC call
             (name, symbol, decimals) = match multicall.call().await {
                 Ok((a, b, c)) => (a, b, c),
-                Err(_) => ("".to_owned(), "".to_owned(), U256::from_dec_str("0")?),
+                Err(_) => ("".to_owned(), "".to_owned(), U256::zero()),
             };
         }
         Standard::Eip721 => {
@@ -216,7 +480,7 @@ async fn get_token_name_and_symbol(
                 Ok((a, b)) => (a, b),
                 Err(_) => ("".to_owned(), "".to_owned()),
             };
-            decimals = U256::from_dec_str("0")?;
+            decimals = U256::zero();
         }
         Standard::Eip1155 => {
             multicall.add_call(name_fn, true);
@@ -226,9 +490,9 @@ async fn get_token_name_and_symbol(
                 Err(_) => "".to_owned(),
             };
             symbol = "".to_owned();
-            decimals = U256::from_dec_str("0")?;
+            decimals = U256::zero();
         }
-        Standard::None => {
+        Standard::None | Standard::Native => {
             multicall
                 .add_call(name_fn, true)
                 .add_call(symbol_fn, true)
@@ -236,9 +500,149 @@ async fn get_token_name_and_symbol(
             // `await`ing on the `call` method lets us fetch the return values of both the above calls in one single RPC call
             (name, symbol, decimals) = match multicall.call().await {
                 Ok((a, b, c)) => (a, b, c),
-                Err(_) => ("".to_owned(), "".to_owned(), U256::from_dec_str("0")?),
+                Err(_) => ("".to_owned(), "".to_owned(), U256::zero()),
             }
         }
     }
     Ok((name, symbol, decimals))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::simulator::test_utils::{log, result, token, uints};
+    use ethers::abi::encode;
+
+    #[test]
+    fn expands_transfer_batch_into_id_amount_pairs() {
+        let log: RawLog =
+            serde_json::from_str(include_str!("fixtures/transfer_batch_log.json")).unwrap();
+
+        let transfers = decode_transfer_batch(&log);
+
+        // the fixture is hand-made, it must at least be laid out the way solidity emits the event
+        let array = |values: &[u64]| {
+            Token::Array(values.iter().map(|v| Token::Uint(U256::from(*v))).collect())
+        };
+        assert_eq!(
+            log.data.to_vec(),
+            encode(&[array(&[1, 2, 3]), array(&[10, 1, 5])])
+        );
+        assert_eq!(
+            transfers,
+            vec![
+                (U256::from(1), U256::from(10)),
+                (U256::from(2), U256::from(1)),
+                (U256::from(3), U256::from(5))
+            ]
+        );
+
+        // one id too many
+        let malformed = RawLog {
+            data: encode(&[
+                Token::Array(vec![Token::Uint(U256::one()), Token::Uint(U256::from(2))]),
+                Token::Array(vec![Token::Uint(U256::one())]),
+            ])
+            .into(),
+            ..log
+        };
+        assert!(decode_transfer_batch(&malformed).is_empty());
+    }
+
+    #[test]
+    fn tells_standards_apart_by_topic_count() {
+        // what the topics hold doesn't matter, only how many there are
+        let holder = Address::repeat_byte(0x11);
+        let approved = encode(&[Token::Bool(true)]);
+
+        let erc20_transfer = log(TRANSFER, &[holder; 2], uints(&[10]));
+        let erc721_transfer = log(TRANSFER, &[holder; 3], Vec::new());
+        let erc721_approval = log(APPROVAL, &[holder; 3], Vec::new());
+        let erc20_approval = log(APPROVAL, &[holder; 2], uints(&[10]));
+        let transfer_single = log(TRANSFER_SINGLE, &[holder; 3], uints(&[10, 10]));
+        let approval_for_all = log(APPROVAL_FOR_ALL, &[holder; 2], approved.clone());
+        // with unindexed addresses
+        let odd_transfer_single = log(TRANSFER_SINGLE, &[holder], uints(&[10, 10]));
+        let odd_approval_for_all = log(APPROVAL_FOR_ALL, &[holder], approved);
+        // a Transfer with its amount indexed matches neither standard
+        let odd_transfer = log(TRANSFER, &[holder; 3], uints(&[1]));
+
+        let standard = |log: &RawLog| standard_from_shape(log.topics[0].to_fixed_bytes(), log);
+        assert_eq!(standard(&erc20_transfer), Some(Standard::Eip20));
+        assert_eq!(standard(&erc20_approval), Some(Standard::Eip20));
+        assert_eq!(standard(&erc721_transfer), Some(Standard::Eip721));
+        assert_eq!(standard(&erc721_approval), Some(Standard::Eip721));
+        assert_eq!(standard(&transfer_single), Some(Standard::Eip1155));
+        assert_eq!(standard(&odd_transfer), Some(Standard::None));
+        assert_eq!(standard(&odd_transfer_single), Some(Standard::None));
+        assert_eq!(standard(&odd_approval_for_all), Some(Standard::None));
+        // only the contract knows whether it is an ERC721 or an ERC1155
+        assert_eq!(standard(&approval_for_all), None);
+    }
+
+    #[tokio::test]
+    async fn skips_events_whose_data_isnt_the_standards() {
+        // nothing is decoded, so nothing is asked of the node
+        let provider = Provider::<Http>::try_from("http://localhost:8545").unwrap();
+        let context = DecodeContext::new(&provider, BlockNumber::Latest);
+        let holder = Address::repeat_byte(0x11);
+
+        let short_transfer_single = log(TRANSFER_SINGLE, &[holder; 3], uints(&[1]));
+        let long_approval_for_all = log(APPROVAL_FOR_ALL, &[holder; 2], uints(&[1, 1]));
+
+        for (log, standard) in [
+            (short_transfer_single, Standard::Eip1155),
+            (long_approval_for_all, Standard::Eip721),
+        ] {
+            assert!(token_event(&log, &context, standard)
+                .await
+                .unwrap()
+                .is_empty());
+        }
+    }
+
+    #[test]
+    fn transfers_from_or_to_nowhere_change_supply() {
+        let transfer = |operation: Operation, standard: Standard, from: Address, to: Address| {
+            result(operation, &token(standard, 0xab), from, to, None, 1)
+        };
+        let (user, sink) = (Address::repeat_byte(0x11), Address::repeat_byte(0x5e));
+        let dead = Address::from(DEAD_ADDRESS);
+        let zero = Address::zero();
+
+        let mut results = [
+            transfer(Operation::Transfer, Standard::Eip20, zero, user),
+            transfer(Operation::Transfer, Standard::Eip721, user, zero),
+            transfer(Operation::TransferSingle, Standard::Eip1155, user, dead),
+            transfer(Operation::TransferBatch, Standard::Eip1155, user, sink),
+            transfer(Operation::Transfer, Standard::Eip20, user, sink),
+            // untouched
+            transfer(
+                Operation::Transfer,
+                Standard::Eip20,
+                user,
+                Address::repeat_byte(0x22),
+            ),
+            transfer(Operation::Approval, Standard::Eip20, user, zero),
+            transfer(Operation::Transfer, Standard::Native, user, zero),
+        ];
+        classify_supply_changes(&mut results[..4], &[]);
+        classify_supply_changes(&mut results[4..], &[sink]);
+
+        let operations: Vec<&Operation> = results.iter().map(|r| &r.operation).collect();
+        assert_eq!(
+            operations,
+            vec![
+                &Operation::Mint,
+                &Operation::Burn,
+                &Operation::Burn,
+                // only a burn address once configured
+                &Operation::TransferBatch,
+                &Operation::Burn,
+                &Operation::Transfer,
+                &Operation::Approval,
+                &Operation::Transfer
+            ]
+        );
+    }
+}
@@ -0,0 +1,284 @@
+use ethers::{
+    abi::Token,
+    contract::Multicall,
+    prelude::abigen,
+    providers::{Http, Middleware, Provider},
+    types::{Address, Bytes, I256, U256},
+};
+use std::sync::Arc;
+
+use super::error::{Result, SimulationError};
+use super::types::{
+    AssetChanges, BalanceCheck, NftAmount, Operation, SimulationReport, SimulationResults,
+    Standard, TokenInfo,
+};
+use super::utils::u256_to_i256;
+
+// Reads the balance of every address a token's events involve, before and after the transaction mined in `block`,
+// and compares its change with the one `asset_changes` derived from the events. Balances that can't be read (e.g. a
+// token without `balanceOf`) are left out.
+pub async fn balance_checks(
+    report: &SimulationReport,
+    provider: &Provider<Http>,
+    block: u64,
+) -> Result<Vec<BalanceCheck>> {
+    let queries = balance_queries(&report.results);
+    let before = read_balances(&queries, provider, block.saturating_sub(1)).await?;
+    let after = read_balances(&queries, provider, block).await?;
+
+    Ok(queries
+        .into_iter()
+        .zip(before.into_iter().zip(after))
+        .filter_map(|(query, balances)| {
+            let (before, after) = match balances {
+                (Some(before), Some(after)) => (before, after),
+                _ => return None,
+            };
+            let delta = u256_to_i256(after) - u256_to_i256(before);
+            let expected_delta = expected_delta(&report.asset_changes, &query);
+
+            Some(BalanceCheck {
+                address: query.address,
+                token_info: query.token_info.clone(),
+                id: query.id,
+                before,
+                after,
+                delta,
+                expected_delta,
+                mismatch: delta != expected_delta,
+            })
+        })
+        .collect())
+}
+
+// Whether the transaction is the only one of its block. The balances are read at the end of the blocks, so those of
+// a block shared with other transactions (the replayed prefix of a position) move by their transfers too.
+pub async fn mined_alone(provider: &Provider<Http>, block: u64) -> Result<bool> {
+    let block = provider
+        .get_block(block)
+        .await?
+        .ok_or_else(|| SimulationError::Rpc(format!("block {} not found", block)))?;
+
+    Ok(block.transactions.len() == 1)
+}
+
+// A balance to read, the nft id's for nfts
+#[derive(Debug, PartialEq)]
+struct BalanceQuery<'a> {
+    address: Address,
+    token_info: &'a TokenInfo,
+    id: Option<U256>,
+}
+
+// one per address involved in the events of every token whose balances the events move, in the order they show up
+fn balance_queries(results: &[SimulationResults]) -> Vec<BalanceQuery<'_>> {
+    let mut queries: Vec<BalanceQuery> = Vec::new();
+
+    for result in results {
+        let addresses = match result.operation {
+            Operation::Transfer
+            | Operation::TransferSingle
+            | Operation::TransferBatch
+            | Operation::Mint
+            | Operation::Burn => [result.from, result.to],
+            Operation::Wrap => [result.to, result.to],
+            Operation::Unwrap => [result.from, result.from],
+            Operation::Approval
+            | Operation::ApprovalForAll
+            | Operation::Deposit
+            | Operation::Withdraw => continue,
+        };
+        // native ETH has no balanceOf, and a token that couldn't be identified might not have one either
+        let id = match result.token_info.standard {
+            Standard::Eip20 | Standard::Eip4626 => None,
+            Standard::Eip721 | Standard::Eip1155 => result.id,
+            Standard::None | Standard::Native => continue,
+        };
+
+        for address in addresses.into_iter().filter(|a| !a.is_zero()) {
+            let query = BalanceQuery {
+                address,
+                token_info: &result.token_info,
+                id,
+            };
+            let seen = queries.iter().any(|q| {
+                (q.address, q.token_info.address, q.id)
+                    == (query.address, query.token_info.address, query.id)
+            });
+            if !seen {
+                queries.push(query);
+            }
+        }
+    }
+
+    queries
+}
+
+// the change of the queried balance the events account for, nothing when they netted out
+fn expected_delta(asset_changes: &[AssetChanges], query: &BalanceQuery) -> I256 {
+    let token = asset_changes
+        .iter()
+        .find(|changes| changes.address == query.address)
+        .and_then(|changes| {
+            changes.tokens.iter().find(|token| {
+                token.token_info.address == query.token_info.address
+                    && token.token_info.standard == query.token_info.standard
+            })
+        });
+    let token = match token {
+        Some(token) => token,
+        None => return I256::zero(),
+    };
+
+    match query.id {
+        None => token.delta,
+        Some(id) => {
+            let net = |nfts: &[NftAmount]| -> I256 {
+                nfts.iter()
+                    .filter(|nft| nft.id == id)
+                    .fold(I256::zero(), |net, nft| net + u256_to_i256(nft.amount))
+            };
+            net(&token.gained) - net(&token.lost)
+        }
+    }
+}
+
+// the queried balances at the end of `block`, `None` for the ones that couldn't be read
+async fn read_balances(
+    queries: &[BalanceQuery<'_>],
+    provider: &Provider<Http>,
+    block: u64,
+) -> Result<Vec<Option<U256>>> {
+    if queries.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    abigen!(
+        TokenInstance,
+        r#"[
+            function balanceOf(address owner) external view returns (uint256)
+            function ownerOf(uint256 id) external view returns (address)
+        ]"#,
+    );
+    abigen!(
+        MultiTokenInstance,
+        r#"[
+            function balanceOf(address owner, uint256 id) external view returns (uint256)
+        ]"#,
+    );
+
+    let client = Arc::new(provider.clone());
+    let mut multicall = Multicall::new(client.clone(), None)
+        .await
+        .map_err(|e| SimulationError::Rpc(e.to_string()))?
+        .block(block);
+    for query in queries {
+        let token = query.token_info.address;
+        match (query.token_info.standard, query.id) {
+            (Standard::Eip721, Some(id)) => {
+                multicall.add_call(TokenInstance::new(token, client.clone()).owner_of(id), true)
+            }
+            (Standard::Eip1155, Some(id)) => multicall.add_call(
+                MultiTokenInstance::new(token, client.clone()).balance_of(query.address, id),
+                true,
+            ),
+            _ => multicall.add_call(
+                TokenInstance::new(token, client.clone()).balance_of(query.address),
+                true,
+            ),
+        };
+    }
+
+    let balances = multicall
+        .call_raw()
+        .await
+        .map_err(|e| SimulationError::Rpc(e.to_string()))?
+        .into_iter()
+        .zip(queries)
+        .map(|(result, query)| balance(result, query))
+        .collect();
+
+    Ok(balances)
+}
+
+// the balance a query's call returned, `None` when it couldn't be read
+fn balance(result: std::result::Result<Token, Bytes>, query: &BalanceQuery) -> Option<U256> {
+    match result {
+        Ok(Token::Uint(balance)) => Some(balance),
+        // an ERC721 balance is whether the address owns the id
+        Ok(Token::Address(owner)) => Some(U256::from((owner == query.address) as u8)),
+        // `ownerOf` reverts for an id that isn't minted yet or was burned, nobody owns it
+        Err(_) if query.token_info.standard == Standard::Eip721 && query.id.is_some() => {
+            Some(U256::zero())
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::simulator::test_utils::{result, token};
+    use crate::simulator::types::TokenChange;
+
+    #[test]
+    fn queries_every_involved_balance_once() {
+        let (user, pool, vault) = (
+            Address::repeat_byte(0x11),
+            Address::repeat_byte(0x22),
+            Address::repeat_byte(0x33),
+        );
+        let (usdc, punks) = (token(Standard::Eip20, 0xc0), token(Standard::Eip721, 0xc1));
+        let results = vec![
+            result(Operation::Transfer, &usdc, user, pool, None, 1),
+            result(Operation::Transfer, &usdc, pool, user, None, 1),
+            result(Operation::Mint, &punks, Address::zero(), user, Some(9), 1),
+            // moves nothing
+            result(Operation::Approval, &usdc, user, vault, None, 1),
+            result(Operation::Deposit, &usdc, user, vault, None, 1),
+        ];
+
+        let queries: Vec<(Address, Address, Option<U256>)> = balance_queries(&results)
+            .iter()
+            .map(|q| (q.address, q.token_info.address, q.id))
+            .collect();
+
+        assert_eq!(
+            queries,
+            vec![
+                (user, usdc.address, None),
+                (pool, usdc.address, None),
+                (user, punks.address, Some(U256::from(9))),
+            ]
+        );
+
+        // the mint was accounted for, the usdc transfers netted out
+        let asset_changes = vec![AssetChanges {
+            address: user,
+            sender: true,
+            tokens: vec![TokenChange {
+                token_info: punks.clone(),
+                delta: I256::one(),
+                gained: vec![NftAmount {
+                    id: U256::from(9),
+                    amount: U256::one(),
+                }],
+                lost: Vec::new(),
+            }],
+        }];
+        let expected: Vec<I256> = balance_queries(&results)
+            .iter()
+            .map(|query| expected_delta(&asset_changes, query))
+            .collect();
+        assert_eq!(expected, vec![I256::zero(), I256::zero(), I256::one()]);
+
+        // before the mint nobody owned the id, so its `ownerOf` reverted
+        let queries = balance_queries(&results);
+        let read = |result, query| balance(result, &queries[query]);
+        assert_eq!(read(Err(Bytes::new()), 2), Some(U256::zero()));
+        assert_eq!(read(Ok(Token::Address(user)), 2), Some(U256::one()));
+        assert_eq!(read(Ok(Token::Address(pool)), 2), Some(U256::zero()));
+        // a token without `balanceOf` is left out
+        assert_eq!(read(Err(Bytes::new()), 0), None);
+    }
+}
@@ -0,0 +1,167 @@
+use ethers::{
+    core::types::TransactionRequest,
+    providers::{Http, Middleware, Provider},
+    types::{Address, Transaction, H256, U256},
+};
+
+use super::error::{Result, SimulationError};
+use super::types::{BlockNumberType, ReplayOptions, SimulationParams};
+
+// Turns a mined transaction back into simulation params. Unless another block is requested it runs at its original
+// position, on top of its parent block or, when replaying the preceding transactions, at its exact index in its block.
+pub async fn replay_params(
+    provider: &Provider<Http>,
+    tx_hash: H256,
+    options: ReplayOptions,
+) -> Result<SimulationParams> {
+    let tx = provider
+        .get_transaction(tx_hash)
+        .await?
+        .ok_or_else(|| config_error(&format!("transaction {:?} not found", tx_hash)))?;
+    let mined_in = tx
+        .block_number
+        .ok_or_else(|| config_error(&format!("transaction {:?} is still pending", tx_hash)))?
+        .as_u64();
+    let to = tx
+        .to
+        .ok_or_else(|| config_error("contract creations can not be replayed"))?;
+
+    let mut builder = SimulationParams::builder()
+        .from(tx.from)
+        .to(to)
+        .data(tx.input)
+        .value_wei(tx.value)
+        .gas_limit(tx.gas)
+        .backend(options.backend)
+        .trace_namespace(options.trace_namespace);
+    builder =
+        match (options.block_number, options.replay_preceding) {
+            (Some(_), true) => return Err(config_error(
+                "preceding transactions can only be replayed at the transaction's original block",
+            )),
+            (Some(block_number), false) => builder.block(block_number),
+            (None, true) => {
+                let index = tx.transaction_index.ok_or_else(|| {
+                    config_error(&format!("transaction {:?} has no index", tx_hash))
+                })?;
+                builder.position(mined_in, index.as_u64())
+            }
+            (None, false) => builder.block(mined_in.saturating_sub(1)),
+        };
+    if let Some(gas_price) = tx.gas_price {
+        builder = builder.gas_price(gas_price);
+    }
+    if let Some(rpc_url) = options.rpc_url {
+        builder = builder.rpc_url(rpc_url);
+    }
+    for abi_path in options.abi_paths {
+        builder = builder.abi_path(abi_path);
+    }
+    for burn_address in options.burn_addresses {
+        builder = builder.burn_address(burn_address);
+    }
+
+    builder.build()
+}
+
+// A position within a block: the transactions mined before it, and the header fields of the block they (and the
+// simulated transaction) have to run in again
+pub struct BlockPrefix {
+    pub timestamp: U256,
+    pub coinbase: Option<Address>,
+    pub transactions: Vec<TransactionRequest>,
+}
+
+// What has to run on the fork before the simulated transaction, only a position within a block has anything: the
+// transactions mined before that position.
+pub async fn preceding_transactions(
+    provider: &Provider<Http>,
+    block: &BlockNumberType,
+) -> Result<Option<BlockPrefix>> {
+    let (block, index) = match block {
+        BlockNumberType::Position { block, index } => (*block, *index),
+        _ => return Ok(None),
+    };
+
+    let mined = provider
+        .get_block_with_txs(block)
+        .await?
+        .ok_or_else(|| SimulationError::Rpc(format!("block {} not found", block)))?;
+
+    Ok(Some(BlockPrefix {
+        timestamp: mined.timestamp,
+        coinbase: mined.author,
+        transactions: block_prefix(&mined.transactions, index)?,
+    }))
+}
+
+fn block_prefix(transactions: &[Transaction], index: u64) -> Result<Vec<TransactionRequest>> {
+    let prefix = transactions.get(..index as usize).ok_or_else(|| {
+        config_error(&format!(
+            "block only has {} transactions, can't simulate at index {}",
+            transactions.len(),
+            index
+        ))
+    })?;
+
+    Ok(prefix
+        .iter()
+        .map(|tx| {
+            let mut request = TransactionRequest::new()
+                .from(tx.from)
+                .data(tx.input.clone())
+                .value(tx.value)
+                .gas(tx.gas);
+            if let Some(to) = tx.to {
+                request = request.to(to);
+            }
+            if let Some(gas_price) = tx.gas_price {
+                request = request.gas_price(gas_price);
+            }
+            request
+        })
+        .collect())
+}
+
+fn config_error(msg: &str) -> SimulationError {
+    SimulationError::Config(msg.to_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ethers::types::NameOrAddress;
+
+    #[test]
+    fn keeps_transactions_mined_before_the_position() {
+        let transactions: Vec<Transaction> = (1..=3u8)
+            .map(|i| Transaction {
+                hash: H256::repeat_byte(i),
+                from: Address::repeat_byte(i),
+                // the first one deploys a contract
+                to: (i > 1).then(|| Address::repeat_byte(0x10 + i)),
+                value: U256::from(i),
+                gas: U256::from(21_000),
+                gas_price: Some(U256::from(100)),
+                ..Default::default()
+            })
+            .collect();
+
+        let preceding = block_prefix(&transactions, 2).unwrap();
+
+        assert_eq!(preceding.len(), 2);
+        assert_eq!(preceding[0].from, Some(Address::repeat_byte(1)));
+        assert_eq!(preceding[0].to, None);
+        assert_eq!(
+            preceding[1].to,
+            Some(NameOrAddress::Address(Address::repeat_byte(0x12)))
+        );
+        assert_eq!(preceding[1].value, Some(U256::from(2)));
+        assert_eq!(preceding[1].gas, Some(U256::from(21_000)));
+        assert_eq!(preceding[1].gas_price, Some(U256::from(100)));
+
+        // simulating after the last transaction of the block is fine, past it isn't
+        assert_eq!(block_prefix(&transactions, 3).unwrap().len(), 3);
+        assert!(block_prefix(&transactions, 4).is_err());
+    }
+}
@@ -0,0 +1,151 @@
+use ethers::{
+    abi::{decode, Abi, ParamType, Token},
+    types::{Bytes, U256},
+};
+
+use super::constants::{ERROR_SELECTOR, PANIC_SELECTOR};
+use super::types::RevertReason;
+use super::utils::format_token;
+
+// Decodes revert data as `Error(string)`, `Panic(uint256)` or any custom error found in the supplied abis.
+// Returns `None` when the data is empty (e.g. a bare `revert()`).
+pub fn decode_revert(data: &Bytes, abis: &[Abi]) -> Option<RevertReason> {
+    if data.len() < 4 {
+        return match data.is_empty() {
+            true => None,
+            false => Some(RevertReason::Unknown(data.clone())),
+        };
+    }
+
+    let (selector, args) = data.split_at(4);
+
+    if selector == ERROR_SELECTOR {
+        if let Ok(decoded) = decode(&[ParamType::String], args) {
+            if let Some(Token::String(reason)) = decoded.into_iter().next() {
+                return Some(RevertReason::Error(reason));
+            }
+        }
+    } else if selector == PANIC_SELECTOR {
+        if let Ok(decoded) = decode(&[ParamType::Uint(256)], args) {
+            if let Some(Token::Uint(code)) = decoded.into_iter().next() {
+                return Some(RevertReason::Panic {
+                    code,
+                    description: panic_description(code).to_owned(),
+                });
+            }
+        }
+    } else {
+        for error in abis.iter().flat_map(|abi| abi.errors()) {
+            if error.signature()[0..4] != *selector {
+                continue;
+            }
+            if let Ok(decoded) = error.decode(args) {
+                let args = error
+                    .inputs
+                    .iter()
+                    .zip(decoded)
+                    .map(|(param, token)| (param.name.clone(), format_token(&token)))
+                    .collect();
+
+                return Some(RevertReason::Custom {
+                    name: error.name.clone(),
+                    args,
+                });
+            }
+        }
+    }
+
+    Some(RevertReason::Unknown(data.clone()))
+}
+
+// https://docs.soliditylang.org/en/latest/control-structures.html#panic-via-assert-and-error-via-require
+fn panic_description(code: U256) -> &'static str {
+    if code > U256::from(u8::MAX) {
+        return "unknown panic code";
+    }
+
+    match code.as_u32() {
+        0x00 => "generic compiler inserted panic",
+        0x01 => "assertion failed",
+        0x11 => "arithmetic overflow or underflow",
+        0x12 => "division or modulo by zero",
+        0x21 => "invalid enum value",
+        0x22 => "incorrectly encoded storage byte array",
+        0x31 => "pop on an empty array",
+        0x32 => "array index out of bounds",
+        0x41 => "out of memory",
+        0x51 => "call to a zero-initialized internal function",
+        _ => "unknown panic code",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ethers::abi::{encode, ethabi::AbiError, Param};
+
+    #[test]
+    fn decodes_error_string() {
+        let mut data = ERROR_SELECTOR.to_vec();
+        data.extend(encode(&[Token::String("STF".to_owned())]));
+
+        assert_eq!(
+            decode_revert(&Bytes::from(data), &[]),
+            Some(RevertReason::Error("STF".to_owned()))
+        );
+    }
+
+    #[test]
+    fn decodes_panic_code() {
+        let mut data = PANIC_SELECTOR.to_vec();
+        data.extend(encode(&[Token::Uint(U256::from(0x11))]));
+
+        assert_eq!(
+            decode_revert(&Bytes::from(data), &[]),
+            Some(RevertReason::Panic {
+                code: U256::from(0x11),
+                description: "arithmetic overflow or underflow".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn decodes_custom_error_from_abi() {
+        let error = AbiError {
+            name: "InsufficientBalance".to_owned(),
+            inputs: vec![
+                Param {
+                    name: "available".to_owned(),
+                    kind: ParamType::Uint(256),
+                    internal_type: None,
+                },
+                Param {
+                    name: "required".to_owned(),
+                    kind: ParamType::Uint(256),
+                    internal_type: None,
+                },
+            ],
+        };
+        let data = error
+            .encode(&[Token::Uint(U256::from(1)), Token::Uint(U256::from(2))])
+            .unwrap();
+
+        let mut abi = Abi::default();
+        abi.errors.insert(error.name.clone(), vec![error]);
+
+        assert_eq!(
+            decode_revert(&Bytes::from(data.clone()), &[abi]),
+            Some(RevertReason::Custom {
+                name: "InsufficientBalance".to_owned(),
+                args: vec![
+                    ("available".to_owned(), "1".to_owned()),
+                    ("required".to_owned(), "2".to_owned()),
+                ],
+            })
+        );
+        assert_eq!(
+            decode_revert(&Bytes::from(data.clone()), &[]),
+            Some(RevertReason::Unknown(Bytes::from(data)))
+        );
+    }
+}
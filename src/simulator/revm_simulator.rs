@@ -0,0 +1,374 @@
+use ethers::{
+    core::types::TransactionRequest,
+    providers::{Http, Middleware, Provider},
+    types::{BlockId, BlockNumber, Bytes, NameOrAddress, H256, U64},
+};
+use revm::{
+    db::{CacheDB, EthersDB},
+    inspector_handle_register,
+    interpreter::{CallInputs, CallOutcome, CreateInputs, CreateOutcome},
+    primitives::{self, BlockEnv, EVMError, ExecutionResult, Log, TransactTo},
+    Database, Evm, EvmContext, Inspector,
+};
+use std::sync::Arc;
+
+use super::call_tree::NativeTransfer;
+use super::decoder::DecoderRegistry;
+use super::error::{Result, SimulationError};
+use super::types::{BlockNumberType, RawLog, SimulationReport};
+use super::utils::{address_to_revm, revm_to_address, revm_to_u256, u256_to_revm};
+
+// An in-process fork on top of a database that lazily pulls accounts, code and storage from the rpc at the requested
+// block. Like `eth_call`, transactions run against the state and header of that block with fees disabled, so senders
+// don't need to hold any ether for gas. A position within a block runs with that block's header on top of its
+// parent's state.
+pub struct RevmFork {
+    provider: Provider<Http>,
+    db: CacheDB<EthersDB<Provider<Http>>>,
+    block_env: BlockEnv,
+    chain_id: u64,
+    // the block whose state the fork reads
+    state_block: U64,
+    block_number: u64,
+}
+
+impl RevmFork {
+    pub async fn new(provider: &Provider<Http>, block: &BlockNumberType) -> Result<Self> {
+        let state_block = match block.fork_block() {
+            Some(num) => U64::from(num),
+            None => provider.get_block_number().await?,
+        };
+        // a position within a block starts from its parent's state but runs in the block itself
+        let block_number = match block {
+            BlockNumberType::Position { block, .. } => U64::from(*block),
+            _ => state_block,
+        };
+        let header = provider
+            .get_block(BlockId::Number(BlockNumber::Number(block_number)))
+            .await?
+            .ok_or_else(|| SimulationError::Rpc(format!("block {} not found", block_number)))?;
+        let chain_id = provider.get_chainid().await?;
+
+        let ethers_db = EthersDB::new(
+            Arc::new(provider.clone()),
+            Some(BlockId::Number(BlockNumber::Number(state_block))),
+        )
+        .ok_or_else(|| SimulationError::Rpc("could not instantiate fork database".to_owned()))?;
+
+        let mut block_env = BlockEnv {
+            number: u256_to_revm(block_number.as_u64().into()),
+            timestamp: u256_to_revm(header.timestamp),
+            gas_limit: u256_to_revm(header.gas_limit),
+            difficulty: u256_to_revm(header.difficulty),
+            prevrandao: header.mix_hash.map(|h| h.0.into()),
+            ..Default::default()
+        };
+        if let Some(author) = header.author {
+            block_env.coinbase = address_to_revm(author);
+        }
+
+        Ok(RevmFork {
+            provider: provider.clone(),
+            db: CacheDB::new(ethers_db),
+            block_env,
+            chain_id: chain_id.as_u64(),
+            state_block,
+            block_number: block_number.as_u64(),
+        })
+    }
+
+    // commits the transaction to the fork without reporting on it, used to bring the fork to the right state
+    pub fn apply(&mut self, tx: TransactionRequest) -> Result<()> {
+        self.execute(tx, true).map(|_| ())
+    }
+
+    // executes the transaction on the fork, with `commit` its state changes are kept for the following simulations
+    pub async fn simulate(
+        &mut self,
+        tx: TransactionRequest,
+        commit: bool,
+        decoders: &DecoderRegistry,
+    ) -> Result<SimulationReport> {
+        let (result, transfers) = self.execute(tx, commit)?;
+
+        let (success, gas_used, logs, revert_data) = match result {
+            ExecutionResult::Success { gas_used, logs, .. } => (true, gas_used, logs, None),
+            ExecutionResult::Revert { gas_used, output } => {
+                (false, gas_used, Vec::new(), Some(Bytes::from(output.0)))
+            }
+            ExecutionResult::Halt { gas_used, .. } => (false, gas_used, Vec::new(), None),
+        };
+
+        let logs: Vec<RawLog> = logs
+            .into_iter()
+            .enumerate()
+            .map(|(log_index, log)| RawLog {
+                log_index,
+                address: revm_to_address(log.address),
+                topics: log.topics().iter().map(|t| H256::from(t.0)).collect(),
+                data: Bytes::from(log.data.data.0),
+            })
+            .collect();
+
+        // the rpc knows nothing of what ran on the fork, calls read the state the fork started from
+        let decoded = decoders
+            .decode_with_transfers(&logs, transfers, &self.provider, self.state_block.into())
+            .await?;
+
+        Ok(SimulationReport {
+            results: decoded.results,
+            success,
+            gas_used: gas_used.into(),
+            effective_gas_price: None,
+            block_number: Some(self.block_number),
+            tx_hash: None,
+            logs,
+            revert_data,
+            revert_reason: None,
+            rejection: None,
+            call_tree: None,
+            custom_events: decoded.custom_events,
+            swaps: decoded.swaps,
+            sales: decoded.sales,
+            undecoded_logs: decoded.undecoded,
+            asset_changes: Vec::new(),
+            balance_checks: None,
+            balance_checks_skipped: None,
+        })
+    }
+
+    // the native transfers are the ones of the frames that didn't revert
+    fn execute(
+        &mut self,
+        tx: TransactionRequest,
+        commit: bool,
+    ) -> Result<(ExecutionResult, Vec<NativeTransfer>)> {
+        let to = match tx.to {
+            Some(NameOrAddress::Address(a)) => TransactTo::Call(address_to_revm(a)),
+            Some(NameOrAddress::Name(_)) => {
+                return Err(SimulationError::Config("name unsupported".to_owned()))
+            }
+            None => TransactTo::create(),
+        };
+
+        let gas_limit = match tx.gas {
+            Some(gas) => gas.as_u64(),
+            None => self.block_env.gas_limit.to::<u64>(),
+        };
+        let chain_id = self.chain_id;
+        let mut evm = Evm::builder()
+            .with_db(&mut self.db)
+            .with_block_env(self.block_env.clone())
+            .modify_cfg_env(|cfg| cfg.chain_id = chain_id)
+            .modify_tx_env(|t| {
+                t.caller = address_to_revm(tx.from.unwrap_or_default());
+                t.transact_to = to;
+                t.value = u256_to_revm(tx.value.unwrap_or_default());
+                t.data = tx.data.clone().unwrap_or_default().0.into();
+                t.gas_limit = gas_limit;
+            })
+            .with_external_context(NativeTransfers::default())
+            .append_handler_register(inspector_handle_register)
+            .build();
+
+        let result = match commit {
+            true => evm.transact_commit(),
+            false => evm.transact().map(|r| r.result),
+        };
+        let transfers = std::mem::take(&mut evm.context.external.transfers);
+        result.map(|r| (r, transfers)).map_err(|e| match e {
+            EVMError::Database(e) => SimulationError::Rpc(e.to_string()),
+            // anything else means the transaction itself is invalid, e.g. the sender can't cover the value
+            e => SimulationError::Reverted {
+                message: format!("{:?}", e),
+                data: None,
+            },
+        })
+    }
+}
+
+// Records the ETH moved by calls, creations and selfdestructs, the receipt-like execution result only has the logs.
+// What a frame moved is dropped again when it reverts, along with the logs it emitted.
+#[derive(Default)]
+struct NativeTransfers {
+    transfers: Vec<NativeTransfer>,
+    // logs emitted so far by the frames that didn't revert
+    logs: usize,
+    // the transfers and logs recorded before each open frame
+    frames: Vec<(usize, usize)>,
+}
+
+impl NativeTransfers {
+    fn enter(
+        &mut self,
+        from: primitives::Address,
+        to: primitives::Address,
+        value: primitives::U256,
+    ) {
+        self.frames.push((self.transfers.len(), self.logs));
+        // a callcode sends the value to the caller itself
+        if !value.is_zero() && from != to {
+            self.transfers.push(NativeTransfer {
+                logs_before: self.logs,
+                from: revm_to_address(from),
+                to: revm_to_address(to),
+                amount: revm_to_u256(value),
+            });
+        }
+    }
+
+    // the index the frame's own transfer was recorded at, if it had one
+    fn exit(&mut self, ok: bool) -> usize {
+        let (transfers, logs) = self.frames.pop().unwrap_or_default();
+        if !ok {
+            self.transfers.truncate(transfers);
+            self.logs = logs;
+        }
+        transfers
+    }
+}
+
+impl<DB: Database> Inspector<DB> for NativeTransfers {
+    fn log(&mut self, _context: &mut EvmContext<DB>, _log: &Log) {
+        self.logs += 1;
+    }
+
+    fn call(
+        &mut self,
+        _context: &mut EvmContext<DB>,
+        inputs: &mut CallInputs,
+    ) -> Option<CallOutcome> {
+        let transfer = &inputs.transfer;
+        self.enter(transfer.source, transfer.target, transfer.value);
+        None
+    }
+
+    fn call_end(
+        &mut self,
+        _context: &mut EvmContext<DB>,
+        _inputs: &CallInputs,
+        outcome: CallOutcome,
+    ) -> CallOutcome {
+        self.exit(outcome.result.result.is_ok());
+        outcome
+    }
+
+    fn create(
+        &mut self,
+        _context: &mut EvmContext<DB>,
+        inputs: &mut CreateInputs,
+    ) -> Option<CreateOutcome> {
+        // the created address is only known once it's deployed
+        self.enter(inputs.caller, primitives::Address::ZERO, inputs.value);
+        None
+    }
+
+    fn create_end(
+        &mut self,
+        _context: &mut EvmContext<DB>,
+        inputs: &CreateInputs,
+        outcome: CreateOutcome,
+    ) -> CreateOutcome {
+        let index = self.exit(outcome.result.result.is_ok());
+        if let (Some(address), false) = (outcome.address, inputs.value.is_zero()) {
+            if let Some(transfer) = self.transfers.get_mut(index) {
+                transfer.to = revm_to_address(address);
+            }
+        }
+        outcome
+    }
+
+    fn selfdestruct(
+        &mut self,
+        contract: primitives::Address,
+        target: primitives::Address,
+        value: primitives::U256,
+    ) {
+        // a contract destroying itself in favour of itself burns its balance, nothing to report
+        if !value.is_zero() && contract != target {
+            self.transfers.push(NativeTransfer {
+                logs_before: self.logs,
+                from: revm_to_address(contract),
+                to: revm_to_address(target),
+                amount: revm_to_u256(value),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ethers::types::{Address, U256};
+    use revm::{
+        db::EmptyDB,
+        primitives::{AccountInfo, Bytecode},
+    };
+
+    #[test]
+    fn drops_the_transfers_of_reverted_frames() {
+        let (sender, contract, payee, reverter) = (
+            Address::repeat_byte(0x11),
+            Address::repeat_byte(0xaa),
+            Address::repeat_byte(0xbb),
+            Address::repeat_byte(0xcc),
+        );
+        // LOG0, then CALL(gas, target, value, 0, 0, 0, 0) and POP for each of the two targets
+        let call = |target: Address, value: u8| -> Vec<u8> {
+            [
+                &[0x60, 0, 0x60, 0, 0x60, 0, 0x60, 0, 0x60, value, 0x73][..],
+                target.as_bytes(),
+                &[0x5a, 0xf1, 0x50],
+            ]
+            .concat()
+        };
+        let code = [
+            vec![0x60, 0, 0x60, 0, 0xa0],
+            call(payee, 5),
+            call(reverter, 7),
+            vec![0x00],
+        ]
+        .concat();
+
+        let mut db = CacheDB::new(EmptyDB::default());
+        let account = |balance: u64, code: Vec<u8>| {
+            let code = Bytecode::new_raw(code.into());
+            AccountInfo::new(u256_to_revm(balance.into()), 0, code.hash_slow(), code)
+        };
+        db.insert_account_info(address_to_revm(sender), account(1_000, Vec::new()));
+        db.insert_account_info(address_to_revm(contract), account(0, code));
+        // REVERT(0, 0)
+        db.insert_account_info(
+            address_to_revm(reverter),
+            account(0, vec![0x60, 0, 0x60, 0, 0xfd]),
+        );
+
+        let mut evm = Evm::builder()
+            .with_db(&mut db)
+            .modify_tx_env(|t| {
+                t.caller = address_to_revm(sender);
+                t.transact_to = TransactTo::Call(address_to_revm(contract));
+                t.value = u256_to_revm(100.into());
+                t.gas_limit = 1_000_000;
+            })
+            .with_external_context(NativeTransfers::default())
+            .append_handler_register(inspector_handle_register)
+            .build();
+        assert!(evm.transact().unwrap().result.is_success());
+
+        let transfers: Vec<(usize, Address, Address, U256)> = evm
+            .context
+            .external
+            .transfers
+            .iter()
+            .map(|t| (t.logs_before, t.from, t.to, t.amount))
+            .collect();
+        assert_eq!(
+            transfers,
+            vec![
+                (0, sender, contract, U256::from(100)),
+                (1, contract, payee, U256::from(5)),
+            ]
+        );
+    }
+}
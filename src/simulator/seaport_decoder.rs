@@ -0,0 +1,349 @@
+use async_trait::async_trait;
+use ethers::{
+    abi::{decode, ParamType, Token},
+    prelude::abigen,
+    types::{Address, H256, U256},
+};
+use std::collections::{hash_map::Entry, HashMap};
+use std::sync::Arc;
+
+use super::constants::SEAPORT_ORDER_FULFILLED;
+use super::decoder::{DecodeContext, DecodedEvent, EventDecoder};
+use super::error::Result;
+use super::process_logs::{get_token_name_and_symbol, native_token};
+use super::types::{FeeKind, RawLog, Sale, SaleFee, SaleItem, SalePrice, Standard, TokenInfo};
+
+// Seaport OrderFulfilled, one sale per fulfilled order
+pub struct SeaportDecoder;
+
+#[async_trait]
+impl EventDecoder for SeaportDecoder {
+    fn topics(&self) -> Vec<H256> {
+        vec![H256::from(SEAPORT_ORDER_FULFILLED)]
+    }
+
+    async fn decode(
+        &self,
+        log: &RawLog,
+        context: &mut DecodeContext<'_>,
+    ) -> Result<Vec<DecodedEvent>> {
+        let order = match order_fulfilled(log) {
+            Some(order) => order,
+            None => return Ok(Vec::new()),
+        };
+        let (seller, buyer) = sides(&order);
+        let price = price(&order);
+
+        // the same currency or collection usually shows up more than once
+        let mut token_infos = HashMap::new();
+        for item in order.offer.iter().chain(order.consideration.iter()) {
+            if let Entry::Vacant(entry) = token_infos.entry(item.token) {
+                entry.insert(token_info(item, context).await?);
+            }
+        }
+        let sale_item = |item: &OrderItem| SaleItem {
+            token_info: token_infos[&item.token].clone(),
+            id: is_nft(item).then_some(item.id),
+            amount: item.amount,
+            recipient: item.recipient,
+        };
+
+        let royalty_receiver = match order
+            .offer
+            .iter()
+            .chain(order.consideration.iter())
+            .find(|item| is_nft(item))
+        {
+            Some(nft) => {
+                let sale_price = price.map(|(_, total, _)| total).unwrap_or_default();
+                royalty_receiver(nft, sale_price, context).await
+            }
+            None => None,
+        };
+        let fees = fee_items(&order, seller)
+            .into_iter()
+            .map(|item| {
+                let recipient = item.recipient.unwrap_or_default();
+                SaleFee {
+                    kind: match Some(recipient) == royalty_receiver {
+                        true => FeeKind::Royalty,
+                        false => FeeKind::Marketplace,
+                    },
+                    recipient,
+                    token_info: token_infos[&item.token].clone(),
+                    amount: item.amount,
+                }
+            })
+            .collect();
+
+        Ok(vec![DecodedEvent::Sale(Sale {
+            log_index: log.log_index,
+            marketplace: log.address,
+            order_hash: order.order_hash,
+            offerer: order.offerer,
+            recipient: order.recipient,
+            seller,
+            buyer,
+            offer: order.offer.iter().map(sale_item).collect(),
+            consideration: order.consideration.iter().map(sale_item).collect(),
+            fees,
+            price: price.map(|(token, total, per_item)| SalePrice {
+                token_info: token_infos[&token].clone(),
+                total,
+                per_item,
+            }),
+        })])
+    }
+}
+
+// An order as OrderFulfilled logs it
+#[derive(Debug, PartialEq)]
+struct FulfilledOrder {
+    order_hash: H256,
+    offerer: Address,
+    recipient: Address,
+    offer: Vec<OrderItem>,
+    consideration: Vec<OrderItem>,
+}
+
+// A SpentItem (offer) or ReceivedItem (consideration), criteria based items being logged with the id they resolved to
+#[derive(Debug, PartialEq)]
+struct OrderItem {
+    standard: Standard,
+    // the zero address for the native currency
+    token: Address,
+    id: U256,
+    amount: U256,
+    // only consideration items have one
+    recipient: Option<Address>,
+}
+
+// `OrderFulfilled(bytes32 orderHash, address indexed offerer, address indexed zone, address recipient,
+// SpentItem[] offer, ReceivedItem[] consideration)`, `None` when the log isn't shaped like it
+fn order_fulfilled(log: &RawLog) -> Option<FulfilledOrder> {
+    if log.topics.len() != 3 {
+        return None;
+    }
+    let spent_item = vec![
+        ParamType::Uint(8),
+        ParamType::Address,
+        ParamType::Uint(256),
+        ParamType::Uint(256),
+    ];
+    let received_item = [spent_item.clone(), vec![ParamType::Address]].concat();
+    let decoded = decode(
+        &[
+            ParamType::FixedBytes(32),
+            ParamType::Address,
+            ParamType::Array(Box::new(ParamType::Tuple(spent_item))),
+            ParamType::Array(Box::new(ParamType::Tuple(received_item))),
+        ],
+        &log.data,
+    )
+    .ok()?;
+
+    let items = |token: &Token| -> Option<Vec<OrderItem>> {
+        token
+            .clone()
+            .into_array()?
+            .into_iter()
+            .map(order_item)
+            .collect()
+    };
+    let (order_hash, recipient) = match (&decoded[0], &decoded[1]) {
+        (Token::FixedBytes(hash), Token::Address(recipient)) => {
+            (H256::from_slice(hash), *recipient)
+        }
+        _ => return None,
+    };
+
+    Some(FulfilledOrder {
+        order_hash,
+        offerer: Address::from(log.topics[1]),
+        recipient,
+        offer: items(&decoded[2])?,
+        consideration: items(&decoded[3])?,
+    })
+}
+
+// `None` for an item type Seaport doesn't have
+fn order_item(token: Token) -> Option<OrderItem> {
+    let fields = token.into_tuple()?;
+
+    let uint = |i: usize| fields.get(i)?.clone().into_uint();
+    let address = |i: usize| fields.get(i)?.clone().into_address();
+    // NATIVE, ERC20, ERC721, ERC1155, ERC721_WITH_CRITERIA, ERC1155_WITH_CRITERIA, anything past them could overflow
+    // a u64
+    let item_type = uint(0)?;
+    if item_type > U256::from(5) {
+        return None;
+    }
+    let standard = match item_type.as_u64() {
+        0 => Standard::Native,
+        1 => Standard::Eip20,
+        2 | 4 => Standard::Eip721,
+        _ => Standard::Eip1155,
+    };
+
+    Some(OrderItem {
+        standard,
+        token: address(1)?,
+        id: uint(2)?,
+        amount: uint(3)?,
+        recipient: match fields.len() {
+            5 => Some(address(4)?),
+            _ => None,
+        },
+    })
+}
+
+fn is_nft(item: &OrderItem) -> bool {
+    matches!(item.standard, Standard::Eip721 | Standard::Eip1155)
+}
+
+// (seller, buyer), an order offering nothing but currency for nfts being a bid
+fn sides(order: &FulfilledOrder) -> (Address, Address) {
+    let is_bid = !order.offer.iter().any(is_nft) && order.consideration.iter().any(is_nft);
+    match is_bid {
+        true => (order.recipient, order.offerer),
+        false => (order.offerer, order.recipient),
+    }
+}
+
+// The currency, total and price per nft of a sale of nfts for a single currency. A listing is paid by its
+// consideration (the seller's proceeds and the fees), a bid by its offer, the fees then coming out of it.
+fn price(order: &FulfilledOrder) -> Option<(Address, U256, U256)> {
+    let payment = match order.offer.iter().any(is_nft) {
+        true => &order.consideration,
+        false => &order.offer,
+    };
+    let payment: Vec<&OrderItem> = payment.iter().filter(|item| !is_nft(item)).collect();
+    let token = payment.first()?.token;
+    if payment.iter().any(|item| item.token != token) {
+        return None;
+    }
+
+    let nfts: U256 = order
+        .offer
+        .iter()
+        .chain(order.consideration.iter())
+        .filter(|item| is_nft(item))
+        .fold(U256::zero(), |count, item| count + item.amount);
+    if nfts.is_zero() {
+        return None;
+    }
+    let total = payment
+        .iter()
+        .fold(U256::zero(), |total, item| total + item.amount);
+
+    Some((token, total, total / nfts))
+}
+
+// the currency the consideration sends to anyone but the seller
+fn fee_items(order: &FulfilledOrder, seller: Address) -> Vec<&OrderItem> {
+    order
+        .consideration
+        .iter()
+        .filter(|item| !is_nft(item) && item.recipient != Some(seller))
+        .collect()
+}
+
+async fn token_info(item: &OrderItem, context: &DecodeContext<'_>) -> Result<TokenInfo> {
+    if item.standard == Standard::Native {
+        return Ok(native_token());
+    }
+    let (name, symbol, decimals) = get_token_name_and_symbol(
+        item.token,
+        context.provider.clone(),
+        context.block,
+        &item.standard,
+    )
+    .await?;
+
+    Ok(TokenInfo {
+        standard: item.standard,
+        address: item.token,
+        name,
+        symbol,
+        decimals,
+    })
+}
+
+// who the nft's ERC-2981 `royaltyInfo` pays, `None` for nfts without royalty info
+async fn royalty_receiver(
+    nft: &OrderItem,
+    sale_price: U256,
+    context: &DecodeContext<'_>,
+) -> Option<Address> {
+    abigen!(
+        RoyaltyInstance,
+        r#"[
+            function royaltyInfo(uint256 tokenId, uint256 salePrice) external view returns (address, uint256)
+        ]"#,
+    );
+
+    RoyaltyInstance::new(nft.token, Arc::new(context.provider.clone()))
+        .royalty_info(nft.id, sale_price)
+        .block(context.block)
+        .call()
+        .await
+        .ok()
+        .map(|(receiver, _)| receiver)
+        .filter(|receiver| !receiver.is_zero())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reads_a_bid_accepted_on_seaport() {
+        // the nft test case of main.rs: a WETH bid for 2 of an ERC1155, accepted with a marketplace fee
+        let log: RawLog =
+            serde_json::from_str(include_str!("fixtures/order_fulfilled_log.json")).unwrap();
+        let address = |s: &str| s.parse::<Address>().unwrap();
+        let weth = address("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2");
+        let bidder = address("0x0b818dc9d41732617dfc5bc8dff03dac632780e1");
+        let fulfiller = address("0x77c5d44f392dd825a073c417ede8c2f8bce603f6");
+        let fee_recipient = address("0x0000a26b00c1f0df003000390027140000faa719");
+
+        let order = order_fulfilled(&log).unwrap();
+
+        assert_eq!((order.offerer, order.recipient), (bidder, fulfiller));
+        assert_eq!(order.offer.len(), 1);
+        assert_eq!(
+            order.consideration[0],
+            OrderItem {
+                standard: Standard::Eip1155,
+                token: address("0x76be3b62873462d2142405439777e971754e8e77"),
+                id: U256::from(10284),
+                amount: U256::from(2),
+                recipient: Some(bidder),
+            }
+        );
+
+        // the bidder buys what the fulfiller sells
+        assert_eq!(sides(&order), (fulfiller, bidder));
+        let total = U256::exp10(16) * 6;
+        assert_eq!(price(&order), Some((weth, total, total / 2)));
+        let fees: Vec<(Option<Address>, U256)> = fee_items(&order, fulfiller)
+            .iter()
+            .map(|item| (item.recipient, item.amount))
+            .collect();
+        assert_eq!(fees, vec![(Some(fee_recipient), U256::exp10(15) * 15 / 10)]);
+
+        // an unknown item type, one too large for a u64 and data that isn't an order are left alone
+        let item_type = 6 * 32 - 1;
+        let with_data = |data: Vec<u8>| RawLog {
+            data: data.into(),
+            ..log.clone()
+        };
+        let mut unknown = log.data.to_vec();
+        unknown[item_type] = 9;
+        let mut overflowing = log.data.to_vec();
+        overflowing[item_type - 8] = 1;
+        assert!(order_fulfilled(&with_data(unknown)).is_none());
+        assert!(order_fulfilled(&with_data(overflowing)).is_none());
+        assert!(order_fulfilled(&with_data(log.data[..64].to_vec())).is_none());
+    }
+}
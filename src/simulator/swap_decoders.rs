@@ -0,0 +1,379 @@
+use async_trait::async_trait;
+use ethers::{
+    prelude::abigen,
+    types::{Address, H256, I256, U256},
+};
+use std::sync::Arc;
+
+use super::constants::{
+    BALANCER_SWAP, CURVE_CRYPTO_TOKEN_EXCHANGE, CURVE_TOKEN_EXCHANGE, UNISWAP_V2_SWAP,
+    UNISWAP_V3_SWAP,
+};
+use super::decoder::{DecodeContext, DecodedEvent, EventDecoder};
+use super::error::Result;
+use super::process_logs::get_token_name_and_symbol;
+use super::types::{RawLog, Standard, Swap, SwapProtocol, TokenInfo};
+
+// Uniswap V2 (and its forks) pair Swap
+pub struct UniswapV2Decoder;
+
+// Uniswap V3 (and its forks) pool Swap
+pub struct UniswapV3Decoder;
+
+// Curve TokenExchange, of both the stable pools (int128 coin ids) and the crypto pools (uint256 coin ids)
+pub struct CurveDecoder;
+
+// Balancer V2 Vault Swap
+pub struct BalancerDecoder;
+
+#[async_trait]
+impl EventDecoder for UniswapV2Decoder {
+    fn topics(&self) -> Vec<H256> {
+        vec![H256::from(UNISWAP_V2_SWAP)]
+    }
+
+    async fn decode(
+        &self,
+        log: &RawLog,
+        context: &mut DecodeContext<'_>,
+    ) -> Result<Vec<DecodedEvent>> {
+        swap(log, uniswap_v2_swap(log), SwapProtocol::UniswapV2, context).await
+    }
+}
+
+#[async_trait]
+impl EventDecoder for UniswapV3Decoder {
+    fn topics(&self) -> Vec<H256> {
+        vec![H256::from(UNISWAP_V3_SWAP)]
+    }
+
+    async fn decode(
+        &self,
+        log: &RawLog,
+        context: &mut DecodeContext<'_>,
+    ) -> Result<Vec<DecodedEvent>> {
+        swap(log, uniswap_v3_swap(log), SwapProtocol::UniswapV3, context).await
+    }
+}
+
+#[async_trait]
+impl EventDecoder for CurveDecoder {
+    fn topics(&self) -> Vec<H256> {
+        vec![
+            H256::from(CURVE_TOKEN_EXCHANGE),
+            H256::from(CURVE_CRYPTO_TOKEN_EXCHANGE),
+        ]
+    }
+
+    async fn decode(
+        &self,
+        log: &RawLog,
+        context: &mut DecodeContext<'_>,
+    ) -> Result<Vec<DecodedEvent>> {
+        swap(log, curve_exchange(log), SwapProtocol::Curve, context).await
+    }
+}
+
+#[async_trait]
+impl EventDecoder for BalancerDecoder {
+    fn topics(&self) -> Vec<H256> {
+        vec![H256::from(BALANCER_SWAP)]
+    }
+
+    async fn decode(
+        &self,
+        log: &RawLog,
+        context: &mut DecodeContext<'_>,
+    ) -> Result<Vec<DecodedEvent>> {
+        swap(log, balancer_swap(log), SwapProtocol::Balancer, context).await
+    }
+}
+
+// A swap as its event tells it, before asking the pool which tokens it swapped
+#[derive(Debug, PartialEq)]
+struct SwapEvent {
+    pool: Address,
+    sender: Option<Address>,
+    recipient: Option<Address>,
+    token_in: PoolToken,
+    amount_in: U256,
+    token_out: PoolToken,
+    amount_out: U256,
+}
+
+#[derive(Debug, PartialEq)]
+enum PoolToken {
+    // the token's position in the pool, Uniswap's token0 and token1 or Curve's coins
+    Index(u64),
+    Address(Address),
+}
+
+// the swap with its tokens resolved on the fork, nothing when the event's emitter doesn't answer like a pool
+async fn swap(
+    log: &RawLog,
+    event: Option<SwapEvent>,
+    protocol: SwapProtocol,
+    context: &mut DecodeContext<'_>,
+) -> Result<Vec<DecodedEvent>> {
+    let event = match event {
+        Some(event) => event,
+        None => return Ok(Vec::new()),
+    };
+    let (token_in, token_out) = match (
+        pool_token(event.pool, &event.token_in, protocol, context).await,
+        pool_token(event.pool, &event.token_out, protocol, context).await,
+    ) {
+        (Some(token_in), Some(token_out)) => (token_in, token_out),
+        _ => return Ok(Vec::new()),
+    };
+
+    Ok(vec![DecodedEvent::Swap(Swap {
+        log_index: log.log_index,
+        protocol,
+        pool: event.pool,
+        sender: event.sender,
+        recipient: event.recipient,
+        token_in: token_info(token_in, context).await?,
+        amount_in: event.amount_in,
+        token_out: token_info(token_out, context).await?,
+        amount_out: event.amount_out,
+    })])
+}
+
+async fn token_info(address: Address, context: &DecodeContext<'_>) -> Result<TokenInfo> {
+    let (name, symbol, decimals) = get_token_name_and_symbol(
+        address,
+        context.provider.clone(),
+        context.block,
+        &Standard::Eip20,
+    )
+    .await?;
+
+    Ok(TokenInfo {
+        standard: Standard::Eip20,
+        address,
+        name,
+        symbol,
+        decimals,
+    })
+}
+
+async fn pool_token(
+    pool: Address,
+    token: &PoolToken,
+    protocol: SwapProtocol,
+    context: &DecodeContext<'_>,
+) -> Option<Address> {
+    abigen!(
+        PoolInstance,
+        r#"[
+            function token0() external view returns (address)
+            function token1() external view returns (address)
+            function coins(uint256 i) external view returns (address)
+        ]"#,
+    );
+    // the first curve pools take the coin id as an int128
+    abigen!(
+        LegacyCurvePoolInstance,
+        r#"[
+            function coins(int128 i) external view returns (address)
+        ]"#,
+    );
+
+    let client = Arc::new(context.provider.clone());
+    let instance = PoolInstance::new(pool, client.clone());
+    match (token, protocol) {
+        (PoolToken::Address(address), _) => Some(*address),
+        (PoolToken::Index(0), SwapProtocol::UniswapV2 | SwapProtocol::UniswapV3) => {
+            instance.token_0().block(context.block).call().await.ok()
+        }
+        (PoolToken::Index(1), SwapProtocol::UniswapV2 | SwapProtocol::UniswapV3) => {
+            instance.token_1().block(context.block).call().await.ok()
+        }
+        (PoolToken::Index(i), SwapProtocol::Curve) => {
+            match instance
+                .coins(U256::from(*i))
+                .block(context.block)
+                .call()
+                .await
+            {
+                Ok(coin) => Some(coin),
+                Err(_) => LegacyCurvePoolInstance::new(pool, client)
+                    .coins(*i as i128)
+                    .block(context.block)
+                    .call()
+                    .await
+                    .ok(),
+            }
+        }
+        _ => None,
+    }
+}
+
+// the 32 bytes words of the log's data
+fn words(log: &RawLog) -> Vec<U256> {
+    log.data.chunks(32).map(U256::from_big_endian).collect()
+}
+
+// `Swap(address indexed sender, uint256 amount0In, uint256 amount1In, uint256 amount0Out, uint256 amount1Out,
+// address indexed to)`
+fn uniswap_v2_swap(log: &RawLog) -> Option<SwapEvent> {
+    if log.topics.len() != 3 || log.data.len() != 128 {
+        return None;
+    }
+    let words = words(log);
+    let (amount0_in, amount1_in, amount0_out, amount1_out) =
+        (words[0], words[1], words[2], words[3]);
+
+    // whichever token left the pair is the one bought
+    let (token_in, amount_in, token_out, amount_out) = match amount0_out.is_zero() {
+        true => (0, amount0_in, 1, amount1_out),
+        false => (1, amount1_in, 0, amount0_out),
+    };
+
+    Some(SwapEvent {
+        pool: log.address,
+        sender: Some(Address::from(log.topics[1])),
+        recipient: Some(Address::from(log.topics[2])),
+        token_in: PoolToken::Index(token_in),
+        amount_in,
+        token_out: PoolToken::Index(token_out),
+        amount_out,
+    })
+}
+
+// `Swap(address indexed sender, address indexed recipient, int256 amount0, int256 amount1, uint160 sqrtPriceX96,
+// uint128 liquidity, int24 tick)`, the amounts being what the pool received (positive) or sent (negative)
+fn uniswap_v3_swap(log: &RawLog) -> Option<SwapEvent> {
+    if log.topics.len() != 3 || log.data.len() != 160 {
+        return None;
+    }
+    let words = words(log);
+    let (amount0, amount1) = (I256::from_raw(words[0]), I256::from_raw(words[1]));
+
+    let (token_in, amount_in, token_out, amount_out) = match amount0.is_positive() {
+        true => (0, amount0, 1, amount1),
+        false => (1, amount1, 0, amount0),
+    };
+
+    Some(SwapEvent {
+        pool: log.address,
+        sender: Some(Address::from(log.topics[1])),
+        recipient: Some(Address::from(log.topics[2])),
+        token_in: PoolToken::Index(token_in),
+        amount_in: amount_in.unsigned_abs(),
+        token_out: PoolToken::Index(token_out),
+        amount_out: amount_out.unsigned_abs(),
+    })
+}
+
+// `TokenExchange(address indexed buyer, int128 sold_id, uint256 tokens_sold, int128 bought_id, uint256
+// tokens_bought)`, crypto pools having uint256 ids
+fn curve_exchange(log: &RawLog) -> Option<SwapEvent> {
+    if log.topics.len() != 2 || log.data.len() != 128 {
+        return None;
+    }
+    let words = words(log);
+    // pools hold a handful of coins, anything bigger isn't a coin id
+    let coin = |id: U256| (id < U256::from(8)).then(|| PoolToken::Index(id.as_u64()));
+    let buyer = Address::from(log.topics[1]);
+
+    Some(SwapEvent {
+        pool: log.address,
+        sender: Some(buyer),
+        recipient: Some(buyer),
+        token_in: coin(words[0])?,
+        amount_in: words[1],
+        token_out: coin(words[2])?,
+        amount_out: words[3],
+    })
+}
+
+// `Swap(bytes32 indexed poolId, address indexed tokenIn, address indexed tokenOut, uint256 amountIn,
+// uint256 amountOut)`, the pool id starting with the pool's address
+fn balancer_swap(log: &RawLog) -> Option<SwapEvent> {
+    if log.topics.len() != 4 || log.data.len() != 64 {
+        return None;
+    }
+    let words = words(log);
+
+    Some(SwapEvent {
+        pool: Address::from_slice(&log.topics[1][..20]),
+        sender: None,
+        recipient: None,
+        token_in: PoolToken::Address(Address::from(log.topics[2])),
+        amount_in: words[0],
+        token_out: PoolToken::Address(Address::from(log.topics[3])),
+        amount_out: words[1],
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reads_swap_direction_and_amounts() {
+        let logs: Vec<RawLog> =
+            serde_json::from_str(include_str!("fixtures/swap_logs.json")).unwrap();
+        let (router, user, pool) = (
+            Address::repeat_byte(0x77),
+            Address::repeat_byte(0x11),
+            Address::repeat_byte(0xaa),
+        );
+
+        // sold 1000 of token1 for 990 of token0
+        assert_eq!(
+            uniswap_v2_swap(&logs[0]),
+            Some(SwapEvent {
+                pool,
+                sender: Some(router),
+                recipient: Some(user),
+                token_in: PoolToken::Index(1),
+                amount_in: U256::from(1000),
+                token_out: PoolToken::Index(0),
+                amount_out: U256::from(990),
+            })
+        );
+        // the pool received 1000 of token0 and sent 990 of token1
+        assert_eq!(
+            uniswap_v3_swap(&logs[1]),
+            Some(SwapEvent {
+                pool,
+                sender: Some(router),
+                recipient: Some(user),
+                token_in: PoolToken::Index(0),
+                amount_in: U256::from(1000),
+                token_out: PoolToken::Index(1),
+                amount_out: U256::from(990),
+            })
+        );
+        assert_eq!(
+            curve_exchange(&logs[2]),
+            Some(SwapEvent {
+                pool,
+                sender: Some(user),
+                recipient: Some(user),
+                token_in: PoolToken::Index(2),
+                amount_in: U256::from(1000),
+                token_out: PoolToken::Index(0),
+                amount_out: U256::from(990),
+            })
+        );
+        assert_eq!(
+            balancer_swap(&logs[3]),
+            Some(SwapEvent {
+                pool: Address::repeat_byte(0xbb),
+                sender: None,
+                recipient: None,
+                token_in: PoolToken::Address(Address::repeat_byte(0xc1)),
+                amount_in: U256::from(1000),
+                token_out: PoolToken::Address(Address::repeat_byte(0xc2)),
+                amount_out: U256::from(990),
+            })
+        );
+
+        // a V2 shaped Swap isn't a V3 one
+        assert_eq!(uniswap_v3_swap(&logs[0]), None);
+    }
+}
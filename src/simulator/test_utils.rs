@@ -0,0 +1,61 @@
+use ethers::{
+    abi::{encode, Token},
+    types::{Address, H256, U256},
+};
+
+use super::types::{Operation, RawLog, SimulationResults, Standard, TokenInfo};
+
+// Fixtures shared by the unit tests
+
+// a nameless token at `0xbyte...byte`
+pub fn token(standard: Standard, byte: u8) -> TokenInfo {
+    TokenInfo {
+        standard,
+        address: Address::repeat_byte(byte),
+        name: "".to_owned(),
+        symbol: "".to_owned(),
+        decimals: U256::zero(),
+    }
+}
+
+pub fn result(
+    operation: Operation,
+    token_info: &TokenInfo,
+    from: Address,
+    to: Address,
+    id: Option<u64>,
+    amount: u64,
+) -> SimulationResults {
+    SimulationResults {
+        operation,
+        token_info: token_info.clone(),
+        from,
+        to,
+        id: id.map(U256::from),
+        amount: U256::from(amount),
+        batch_log_index: None,
+        underlying: None,
+    }
+}
+
+// the first log of the transaction, emitted by `0xab...ab`
+pub fn log(topic0: [u8; 32], indexed: &[Address], data: Vec<u8>) -> RawLog {
+    RawLog {
+        log_index: 0,
+        address: Address::repeat_byte(0xab),
+        topics: std::iter::once(H256::from(topic0))
+            .chain(indexed.iter().map(|a| H256::from(*a)))
+            .collect(),
+        data: data.into(),
+    }
+}
+
+// the abi encoding of the values as uint256's
+pub fn uints(values: &[u64]) -> Vec<u8> {
+    encode(
+        &values
+            .iter()
+            .map(|v| Token::Uint(U256::from(*v)))
+            .collect::<Vec<_>>(),
+    )
+}
@@ -1,46 +1,334 @@
 use ethers::{
-    core::types::TransactionRequest,
-    providers::{Http, Middleware, Provider},
+    core::types::{transaction::eip2718::TypedTransaction, TransactionRequest},
+    providers::{Http, Middleware, Provider, ProviderError, RpcError},
     types::{
-        Address, BlockId, BlockNumber, Bytes, GethDebugTracingCallOptions, GethDebugTracingOptions,
+        Address, BlockId, BlockNumber, Bytes, GethDebugBuiltInTracerConfig,
+        GethDebugBuiltInTracerType, GethDebugTracerConfig, GethDebugTracerType,
+        GethDebugTracingCallOptions, GethDebugTracingOptions, GethTrace, GethTraceFrame,
         NameOrAddress, StructLog, H256, U256, U64,
     },
+    utils::{self, hex},
 };
-use eyre::Result;
-use std::process;
+use serde::Deserialize;
 
-use super::constants::PRECOMPILES;
-use super::process_logs::process_logs;
-use super::types::{BlockNumberType, MyLog, SimulationResults};
+use super::call_tree::NativeTransfer;
+use super::decoder::DecoderRegistry;
+use super::error::{Result, SimulationError};
+use super::parity_trace;
+use super::types::{
+    BlockNumberType, CallFrame, CallKind, FrameLog, RawLog, SimulationReport, TraceNamespace,
+};
 use super::utils::{u256_to_address, u64_array_to_u8_array /*, write_to_output_file */};
 
+// what a trace tells us about the transaction, whichever tracer produced it
+struct TraceOutcome {
+    failed: bool,
+    gas_used: U256,
+    output: Bytes,
+    logs: Vec<RawLog>,
+    transfers: Vec<NativeTransfer>,
+    call_tree: CallFrame,
+}
+
 pub async fn simulate(
     tx: TransactionRequest,
     provider: &Provider<Http>,
     block: BlockNumberType,
-) -> Result<Vec<SimulationResults>> {
-    let block = match block {
-        BlockNumberType::Past(num) => BlockId::Number(BlockNumber::Number(U64::from(num))),
-        BlockNumberType::Latest => BlockId::Number(BlockNumber::Latest),
+    namespace: TraceNamespace,
+    decoders: &DecoderRegistry,
+) -> Result<SimulationReport> {
+    // pin latest to a concrete number so the report can say where the trace ran
+    let block_number = match block.fork_block() {
+        Some(num) => U64::from(num),
+        None => provider.get_block_number().await?,
+    };
+
+    let to: Address = match tx.to.clone() {
+        Some(NameOrAddress::Address(a)) => a,
+        Some(NameOrAddress::Name(_)) => {
+            return Err(SimulationError::Config("name unsupported".to_owned()))
+        }
+        None => {
+            return Err(SimulationError::Config(
+                "contract creation is not supported in trace mode".to_owned(),
+            ))
+        }
+    };
+
+    let traced = match namespace {
+        TraceNamespace::Debug => debug_trace(tx, provider, block_number, to).await,
+        TraceNamespace::Trace => trace_call(tx, provider, block_number).await,
+        TraceNamespace::Auto => match debug_trace(tx.clone(), provider, block_number, to).await {
+            // no usable debug trace, most likely the node only serves the trace namespace
+            Err(SimulationError::UnsupportedTrace(_)) => {
+                trace_call(tx, provider, block_number).await
+            }
+            traced => traced,
+        },
     };
-    let mut tracing_options = GethDebugTracingOptions::default();
-    tracing_options.enable_memory = Some(true);
-    // tracing_options.tracer = Some(GethDebugTracerType::BuiltInTracer(GethDebugBuiltInTracerType::CallTracer));
-    // tracing_options.tracer_config = Some(GethDebugTracerConfig::BuiltInTracer(
-    //     GethDebugBuiltInTracerConfig::CallTracer(CallConfig {
-    //         with_log: Some(true),
-    //         only_top_call: Some(false),
-    //     }),
-    // ));
-
-    let to: Address = match tx.to.clone().unwrap() {
-        NameOrAddress::Address(a) => a,
-        NameOrAddress::Name(_) => {
-            println!("name unsupported");
-            process::exit(1);
+    let mut outcome = match traced {
+        Ok(outcome) => outcome,
+        Err(err) => {
+            return match err.revert_data() {
+                Some(data) => Ok(SimulationReport::reverted(
+                    data,
+                    Some(block_number.as_u64()),
+                )),
+                None => Err(err),
+            };
         }
     };
 
+    // a reverted transaction's logs and value transfers never make it on chain, so there's nothing to decode
+    if outcome.failed {
+        outcome.logs.clear();
+        outcome.transfers.clear();
+    }
+
+    // nothing was mined, calls read the state the transaction was traced on
+    let decoded = decoders
+        .decode_with_transfers(
+            &outcome.logs,
+            outcome.transfers,
+            provider,
+            block_number.into(),
+        )
+        .await?;
+
+    Ok(SimulationReport {
+        results: decoded.results,
+        success: !outcome.failed,
+        gas_used: outcome.gas_used,
+        effective_gas_price: None,
+        block_number: Some(block_number.as_u64()),
+        tx_hash: None,
+        logs: outcome.logs,
+        revert_data: match outcome.failed {
+            true => Some(outcome.output),
+            false => None,
+        },
+        revert_reason: None,
+        rejection: None,
+        call_tree: Some(outcome.call_tree),
+        custom_events: decoded.custom_events,
+        swaps: decoded.swaps,
+        sales: decoded.sales,
+        undecoded_logs: decoded.undecoded,
+        asset_changes: Vec::new(),
+        balance_checks: None,
+        balance_checks_skipped: None,
+    })
+}
+
+// Traces the transaction with the debug namespace. The call tracer is preferred, rebuilding logs from struct logs
+// needs every step's memory and is only the fallback for nodes that don't ship it.
+async fn debug_trace(
+    tx: TransactionRequest,
+    provider: &Provider<Http>,
+    block_number: U64,
+    to: Address,
+) -> Result<TraceOutcome> {
+    let block = BlockId::Number(BlockNumber::Number(block_number));
+
+    match trace_call_tree(tx.clone(), provider, block).await? {
+        Some(call_tree) => Ok(call_tree_outcome(call_tree)),
+        None => trace_struct_logs(tx, provider, block, to).await,
+    }
+}
+
+// Traces the transaction with geth's built-in `callTracer`, `None` when the node doesn't have it. The request is sent
+// by hand because ethers' call frame drops the `position` of logs, which is what orders them among the frame's
+// subcalls.
+async fn trace_call_tree(
+    tx: TransactionRequest,
+    provider: &Provider<Http>,
+    block: BlockId,
+) -> Result<Option<CallFrame>> {
+    let tx: TypedTransaction = tx.into();
+    let params = [
+        utils::serialize(&tx),
+        utils::serialize(&block),
+        utils::serialize(&GethDebugTracingCallOptions {
+            tracing_options: call_tracer_options(),
+            state_overrides: None,
+        }),
+    ];
+
+    let frame: serde_json::Value = match provider.request("debug_traceCall", params).await {
+        Ok(frame) => frame,
+        Err(err) => {
+            return match err.as_error_response() {
+                Some(e) if lacks_call_tracer(e.code, &e.message) => Ok(None),
+                _ => Err(debug_trace_error(err)),
+            }
+        }
+    };
+    let frame: GethCallFrame = serde_json::from_value(frame).map_err(|e| {
+        SimulationError::UnsupportedTrace(format!("unexpected call tracer output: {}", e))
+    })?;
+
+    Ok(Some(frame.into()))
+}
+
+// The call tree of a transaction mined on the fork, `None` when the node can't trace it. Only the native transfers
+// are missing from the receipt, so not getting them isn't worth failing a simulation that already ran.
+pub async fn mined_call_tree(tx_hash: H256, provider: &Provider<Http>) -> Option<CallFrame> {
+    let params = [
+        utils::serialize(&tx_hash),
+        utils::serialize(&call_tracer_options()),
+    ];
+    let frame: serde_json::Value = provider
+        .request("debug_traceTransaction", params)
+        .await
+        .ok()?;
+    let frame: GethCallFrame = serde_json::from_value(frame).ok()?;
+
+    Some(frame.into())
+}
+
+fn call_tracer_options() -> GethDebugTracingOptions {
+    GethDebugTracingOptions {
+        tracer: Some(GethDebugTracerType::BuiltInTracer(
+            GethDebugBuiltInTracerType::CallTracer,
+        )),
+        tracer_config: Some(GethDebugTracerConfig::BuiltInTracer(
+            GethDebugBuiltInTracerConfig::CallTracer(ethers::types::CallConfig {
+                with_log: Some(true),
+                only_top_call: Some(false),
+            }),
+        )),
+        ..Default::default()
+    }
+}
+
+// json-rpc's "method not found", or geth's answer to a tracer it doesn't know
+fn lacks_call_tracer(code: i64, message: &str) -> bool {
+    let message = message.to_lowercase();
+    code == -32601 || message.contains("tracer not found") || message.contains("unknown tracer")
+}
+
+// Traces the transaction with the trace namespace, its logs rebuilt from the vm trace
+async fn trace_call(
+    tx: TransactionRequest,
+    provider: &Provider<Http>,
+    block_number: U64,
+) -> Result<TraceOutcome> {
+    let call_tree = parity_trace::trace_call(tx, provider, block_number.into()).await?;
+
+    Ok(call_tree_outcome(call_tree))
+}
+
+// a node without the debug namespace answers with json-rpc's "method not found"
+fn debug_trace_error(err: ProviderError) -> SimulationError {
+    match err.as_error_response() {
+        Some(e) if e.code == -32601 => {
+            SimulationError::UnsupportedTrace(format!("debug_traceCall unavailable: {}", e.message))
+        }
+        _ => SimulationError::from_rpc_revert(err),
+    }
+}
+
+fn call_tree_outcome(call_tree: CallFrame) -> TraceOutcome {
+    let (logs, transfers) = call_tree.flatten();
+
+    TraceOutcome {
+        failed: call_tree.reverted,
+        gas_used: call_tree.gas_used,
+        output: call_tree.output.clone(),
+        logs,
+        transfers,
+        call_tree,
+    }
+}
+
+// a frame of geth's `callTracer` output
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GethCallFrame {
+    #[serde(rename = "type")]
+    typ: String,
+    from: Address,
+    #[serde(default)]
+    to: Option<Address>,
+    #[serde(default)]
+    value: Option<U256>,
+    #[serde(default)]
+    gas_used: U256,
+    #[serde(default)]
+    input: Bytes,
+    #[serde(default)]
+    output: Option<Bytes>,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    calls: Vec<GethCallFrame>,
+    #[serde(default)]
+    logs: Vec<GethCallLog>,
+}
+
+#[derive(Deserialize)]
+struct GethCallLog {
+    address: Address,
+    #[serde(default)]
+    topics: Vec<H256>,
+    #[serde(default)]
+    data: Bytes,
+    // only reported by newer geth versions, logs without it are placed after the frame's subcalls
+    #[serde(default)]
+    position: Option<U64>,
+}
+
+impl From<GethCallFrame> for CallFrame {
+    fn from(frame: GethCallFrame) -> Self {
+        let calls: Vec<CallFrame> = frame.calls.into_iter().map(CallFrame::from).collect();
+        let logs = frame
+            .logs
+            .into_iter()
+            .map(|log| FrameLog {
+                address: log.address,
+                topics: log.topics,
+                data: log.data,
+                position: log.position.map_or(calls.len(), |p| p.as_usize()),
+            })
+            .collect();
+
+        CallFrame {
+            kind: match frame.typ.as_str() {
+                "STATICCALL" => CallKind::StaticCall,
+                "DELEGATECALL" => CallKind::DelegateCall,
+                "CALLCODE" => CallKind::CallCode,
+                "CREATE" => CallKind::Create,
+                "CREATE2" => CallKind::Create2,
+                "SELFDESTRUCT" => CallKind::SelfDestruct,
+                _ => CallKind::Call,
+            },
+            from: frame.from,
+            to: frame.to.unwrap_or_default(),
+            value: frame.value.unwrap_or_default(),
+            gas_used: frame.gas_used,
+            input: frame.input,
+            output: frame.output.unwrap_or_default(),
+            reverted: frame.error.is_some(),
+            calls,
+            logs,
+        }
+    }
+}
+
+// Traces the transaction step by step and rebuilds its logs out of the struct logs' stack and memory
+async fn trace_struct_logs(
+    tx: TransactionRequest,
+    provider: &Provider<Http>,
+    block: BlockId,
+    to: Address,
+) -> Result<TraceOutcome> {
+    let (from, value) = (tx.from.unwrap_or_default(), tx.value.unwrap_or_default());
+    let input = tx.data.clone().unwrap_or_default();
+    let tracing_options = GethDebugTracingOptions {
+        enable_memory: Some(true),
+        ..Default::default()
+    };
+
     let tx_trace = provider
         .debug_trace_call(
             tx,
@@ -51,120 +339,661 @@ pub async fn simulate(
             },
         )
         .await
-        .unwrap_or_else(|e| {
-            eprintln!("transaction reverted with err: {}", e);
-            process::exit(1);
-        });
+        .map_err(debug_trace_error)?;
 
     // write_to_output_file(&tx_trace);
 
     let x = match tx_trace {
-        ethers::types::GethTrace::Known(a) => match a {
-            ethers::types::GethTraceFrame::Default(b) => b,
-            _ => todo!(),
-        },
-        _ => todo!(),
+        GethTrace::Known(GethTraceFrame::Default(b)) => b,
+        other => {
+            return Err(SimulationError::UnsupportedTrace(format!(
+                "expected struct logs, got {:?}",
+                other
+            )))
+        }
     };
 
-    let mut cached_call_stack: Vec<Address> = vec![to];
-    let mut logs_call_stack: Vec<Vec<Address>> = Vec::new();
+    let (logs, transfers, mut call_tree) = trace_logs(&x.struct_logs, from, to, value)?;
+    // what the struct logs can't tell about the transaction itself
+    call_tree.input = input;
+    call_tree.gas_used = x.gas;
+    call_tree.output = x.return_value.clone();
+    call_tree.reverted = x.failed;
 
-    let struct_logs: Vec<StructLog> = x
-        .struct_logs
+    Ok(TraceOutcome {
+        failed: x.failed,
+        gas_used: x.gas,
+        output: x.return_value,
+        logs,
+        transfers,
+        call_tree,
+    })
+}
+
+// Rebuilds the logs and native transfers of a successful execution out of its struct logs, each log attributed to the
+// address of the frame that emitted it. Whatever happened inside a call that failed is dropped. The call tree keeps
+// every call, the transaction's own input, output and gas are left for the caller to fill in.
+fn trace_logs(
+    struct_logs: &[StructLog],
+    from: Address,
+    to: Address,
+    value: U256,
+) -> Result<(Vec<RawLog>, Vec<NativeTransfer>, CallFrame)> {
+    let mut frames = FrameTracker::new(from, to, value);
+    for (step, struct_log) in struct_logs.iter().enumerate() {
+        frames.step(step, struct_log);
+    }
+    let call_tree = frames.call_tree(struct_logs)?;
+    let (emitted, transfers) = frames.finish();
+
+    let logs = emitted
         .into_iter()
-        .filter(|s| {
-            // update call stack
-            match s.op.as_str() {
-                "CALL" | "STATICCALL" => {
-                    let stack = s.stack.as_ref().unwrap();
-                    let called_address = stack[stack.len() - 2];
-
-                    if !PRECOMPILES.contains(&u64_array_to_u8_array(called_address.0)) {
-                        cached_call_stack.push(u256_to_address(called_address));
-                    }
-
-                    false
-                }
-                "RETURN" | "REVERT" | "STOP" => {
-                    cached_call_stack.pop();
-                    false
-                }
-                "LOG3" | "LOG4" => {
-                    logs_call_stack.push(cached_call_stack.clone());
-                    true
-                }
-                _ => false,
-            }
-        })
+        .enumerate()
+        .map(|(log_index, (address, step))| raw_log(log_index, address, &struct_logs[step]))
+        .collect::<Result<Vec<RawLog>>>()?;
+
+    Ok((logs, transfers, call_tree))
+}
+
+fn stack_and_memory(struct_log: &StructLog) -> Result<(&[U256], &[String])> {
+    let stack = struct_log.stack.as_ref().ok_or_else(|| {
+        SimulationError::UnsupportedTrace("struct log is missing its stack".to_owned())
+    })?;
+    let memory = struct_log.memory.as_ref().ok_or_else(|| {
+        SimulationError::UnsupportedTrace("struct log is missing its memory".to_owned())
+    })?;
+    Ok((stack, memory))
+}
+
+// The memory an opcode reads, its offset and size at the given positions from the top of the stack
+fn memory_operand(struct_log: &StructLog, offset: usize, size: usize) -> Result<Bytes> {
+    let (stack, memory) = stack_and_memory(struct_log)?;
+    let value_at = |position: usize| stack.len().checked_sub(position + 1).map(|i| stack[i]);
+
+    match (value_at(offset), value_at(size)) {
+        (Some(offset), Some(size)) => memory_slice(memory, offset, size),
+        _ => Err(SimulationError::UnsupportedTrace(format!(
+            "{} with only {} stack items",
+            struct_log.op,
+            stack.len()
+        ))),
+    }
+}
+
+fn raw_log(log_index: usize, address: Address, struct_log: &StructLog) -> Result<RawLog> {
+    let (stack, memory) = stack_and_memory(struct_log)?;
+
+    // LOG0 to LOG4, the digit is the number of topics
+    let topic_count = struct_log
+        .op
+        .strip_prefix("LOG")
+        .and_then(|n| n.parse::<usize>().ok())
+        .filter(|n| *n <= 4)
+        .ok_or_else(|| {
+            SimulationError::UnsupportedTrace(format!("{} is not a log opcode", struct_log.op))
+        })?;
+    if stack.len() < topic_count + 2 {
+        return Err(SimulationError::UnsupportedTrace(format!(
+            "{} with only {} stack items",
+            struct_log.op,
+            stack.len()
+        )));
+    }
+
+    // memory offset and size of the data are on top of the stack, followed by the topics
+    let mut items = stack.iter().rev();
+    let (offset, size) = (items.next(), items.next());
+    let topics = items
+        .take(topic_count)
+        .map(|topic| H256::from(u64_array_to_u8_array(topic.0)))
         .collect();
 
-    let struct_logs_and_their_call_stack = struct_logs.into_iter().zip(logs_call_stack);
+    let data = match (offset, size) {
+        (Some(offset), Some(size)) => memory_slice(memory, *offset, *size)?,
+        _ => Bytes::default(),
+    };
 
-    let mut logs: Vec<MyLog> = Vec::new();
-    for (struct_log, call_stack) in struct_logs_and_their_call_stack {
-        let stack = struct_log.stack.unwrap();
-        let stack_length = stack.len();
+    Ok(RawLog {
+        log_index,
+        address,
+        topics,
+        data,
+    })
+}
 
-        let memory = struct_log.memory.unwrap();
+// Reads `size` bytes at `offset` out of the memory of a struct log, which geth reports as 32 byte hex words. Like the
+// evm, memory that was never written to reads as zeroes.
+fn memory_slice(memory: &[String], offset: U256, size: U256) -> Result<Bytes> {
+    if size.is_zero() {
+        return Ok(Bytes::default());
+    }
+    if offset.bits() > 32 || size.bits() > 32 {
+        return Err(SimulationError::UnsupportedTrace(
+            "log data is out of the memory's range".to_owned(),
+        ));
+    }
+    let (offset, size) = (offset.as_usize(), size.as_usize());
 
-        // get data
-        let data_word_index = (stack[stack_length - 1] / 32).as_usize();
-        let data_offset = (stack[stack_length - 1] % 32).as_usize();
+    let first_word = offset / 32;
+    let words = (offset + size).div_ceil(32) - first_word;
 
-        let data_len = (stack[stack_length - 2]).as_usize();
+    let mut bytes: Vec<u8> = Vec::with_capacity(words * 32);
+    for word in memory.iter().skip(first_word).take(words) {
+        let word = hex::decode(word.trim_start_matches("0x"))
+            .map_err(|e| SimulationError::Decode(format!("invalid memory word: {}", e)))?;
+        bytes.extend(word);
+    }
+    bytes.resize(words * 32, 0);
 
-        let mut data: Vec<u8> = Vec::new();
+    let start = offset - first_word * 32;
+    Ok(Bytes::from(bytes[start..start + size].to_vec()))
+}
 
-        let count = (data_len / 32) + 1;
-        for i in 0..count {
-            let to_push;
+// a frame that is currently executing
+struct Frame {
+    // index into `FrameTracker::addresses` of the address the frame runs as
+    address: usize,
+    // a created contract only learns its address once the creation returns it to the parent frame
+    creates: bool,
+    // logs and transfers made before the frame started, anything after them is dropped if the frame fails
+    logs_before: usize,
+    transfers_before: usize,
+    // gas at the frame's first step, and left after its latest one
+    gas_start: u64,
+    gas_left: u64,
+    call: TracedCall,
+}
 
-            if i == count - 1 {
-                if data_offset == 0 {
-                    break;
-                }
-                let x = memory[data_word_index + i].as_str();
-                to_push = &x[0..data_offset];
-            } else {
-                to_push = memory[data_word_index + i].as_str();
+// a call or create opcode whose frame hasn't started yet
+struct PendingCall {
+    step: usize,
+    depth: u64,
+    gas: u64,
+    op: String,
+    target: usize,
+    // the address whose code runs, the target but for DELEGATECALL and CALLCODE
+    code: usize,
+    value: U256,
+}
+
+// A frame of the call tree, with addresses as indices into `FrameTracker::addresses` and memory as the struct logs
+// to read it from once the trace is over
+struct TracedCall {
+    kind: CallKind,
+    from: usize,
+    to: usize,
+    address: usize,
+    value: U256,
+    // the opcode that made the call, `None` for the transaction itself
+    call_step: Option<usize>,
+    // the RETURN or REVERT that ended the frame
+    return_step: Option<usize>,
+    gas_used: u64,
+    reverted: bool,
+    calls: Vec<TracedCall>,
+    // number of calls made before the log, and the LOG opcode
+    logs: Vec<(usize, usize)>,
+}
+
+impl TracedCall {
+    fn call_frame(self, addresses: &[Address], struct_logs: &[StructLog]) -> Result<CallFrame> {
+        let input = match self.call_step {
+            Some(step) => {
+                // positions of the input's memory offset and size on the stack
+                let (offset, size) = match self.kind {
+                    CallKind::Call | CallKind::CallCode => (3, 4),
+                    CallKind::DelegateCall | CallKind::StaticCall => (2, 3),
+                    _ => (1, 2),
+                };
+                memory_operand(&struct_logs[step], offset, size)?
             }
+            None => Bytes::default(),
+        };
+        let output = match self.return_step {
+            Some(step) => memory_operand(&struct_logs[step], 0, 1)?,
+            None => Bytes::default(),
+        };
+        let logs = self
+            .logs
+            .into_iter()
+            .map(|(position, step)| {
+                let log = raw_log(0, addresses[self.address], &struct_logs[step])?;
+                Ok(FrameLog {
+                    address: log.address,
+                    topics: log.topics,
+                    data: log.data,
+                    position,
+                })
+            })
+            .collect::<Result<Vec<FrameLog>>>()?;
+        let calls = self
+            .calls
+            .into_iter()
+            .map(|call| call.call_frame(addresses, struct_logs))
+            .collect::<Result<Vec<CallFrame>>>()?;
+
+        Ok(CallFrame {
+            kind: self.kind,
+            from: addresses[self.from],
+            to: addresses[self.to],
+            value: self.value,
+            gas_used: self.gas_used.into(),
+            input,
+            output,
+            reverted: self.reverted,
+            calls,
+            logs,
+        })
+    }
+}
+
+// Follows the call frames of a struct log trace through depth changes rather than opcodes, so frames ending in a
+// RETURN, REVERT, STOP, SELFDESTRUCT or an exceptional halt are all handled the same way. A call into an account
+// without code (or a precompile) never starts a frame and its result is on the next step's stack right away.
+// DELEGATECALL and CALLCODE run as the calling frame's address, CREATE and CREATE2 as the address of the contract
+// being deployed, which is the address they leave on the parent's stack. Ether moves with every CALL and
+// CREATE/CREATE2 value, CALLCODE's value never leaves the calling contract. The gas a frame used is told by the gas
+// left at its first and last steps. SELFDESTRUCT isn't part of the call tree, the balance it sends away isn't in the
+// struct logs.
+struct FrameTracker {
+    // every address a frame or transfer refers to, by index so created addresses can be filled in after the fact
+    addresses: Vec<Address>,
+    frames: Vec<Frame>,
+    pending_call: Option<PendingCall>,
+    // frame address index and struct log index of every surviving log
+    logs: Vec<(usize, usize)>,
+    // logs before, from and to address indices, amount
+    transfers: Vec<(usize, usize, usize, U256)>,
+}
+
+impl FrameTracker {
+    fn new(from: Address, to: Address, value: U256) -> Self {
+        let mut tracker = FrameTracker {
+            addresses: vec![from, to],
+            frames: vec![Frame {
+                address: 1,
+                creates: false,
+                logs_before: 0,
+                transfers_before: 0,
+                gas_start: 0,
+                gas_left: 0,
+                call: TracedCall {
+                    kind: CallKind::Call,
+                    from: 0,
+                    to: 1,
+                    address: 1,
+                    value,
+                    call_step: None,
+                    return_step: None,
+                    gas_used: 0,
+                    reverted: false,
+                    calls: Vec::new(),
+                    logs: Vec::new(),
+                },
+            }],
+            pending_call: None,
+            logs: Vec::new(),
+            transfers: Vec::new(),
+        };
+        if !value.is_zero() {
+            tracker.transfers.push((0, 0, 1, value));
+        }
+        tracker
+    }
+
+    fn step(&mut self, step: usize, s: &StructLog) {
+        let stack: &[U256] = s.stack.as_deref().unwrap_or_default();
+        // the result of a call that just returned, the success flag or the created address
+        let call_result = stack.last().copied().unwrap_or_default();
 
-            let y = u64_array_to_u8_array(U256::from_str_radix(to_push, 16).expect("aaa").0);
+        if let Some(call) = self.pending_call.take() {
+            let current = self.current_address();
+            let transfers_before = self.transfers.len();
+            let moves_value = !call.value.is_zero() && call.op != "CALLCODE";
+            let mut traced = TracedCall {
+                kind: match call.op.as_str() {
+                    "STATICCALL" => CallKind::StaticCall,
+                    "DELEGATECALL" => CallKind::DelegateCall,
+                    "CALLCODE" => CallKind::CallCode,
+                    "CREATE" => CallKind::Create,
+                    "CREATE2" => CallKind::Create2,
+                    _ => CallKind::Call,
+                },
+                from: current,
+                to: call.code,
+                address: call.target,
+                value: call.value,
+                call_step: Some(call.step),
+                return_step: None,
+                gas_used: 0,
+                reverted: false,
+                calls: Vec::new(),
+                logs: Vec::new(),
+            };
+
+            if s.depth == call.depth + 1 {
+                if moves_value {
+                    self.transfers
+                        .push((self.logs.len(), current, call.target, call.value));
+                }
+                self.frames.push(Frame {
+                    address: call.target,
+                    creates: call.op.starts_with("CREATE"),
+                    logs_before: self.logs.len(),
+                    transfers_before,
+                    gas_start: s.gas,
+                    gas_left: s.gas,
+                    call: traced,
+                });
+            } else if s.depth == call.depth {
+                let succeeded = !call_result.is_zero();
+                if succeeded && call.op.starts_with("CREATE") {
+                    self.addresses[call.target] = u256_to_address(call_result);
+                }
+                if moves_value && succeeded {
+                    self.transfers
+                        .push((self.logs.len(), current, call.target, call.value));
+                }
+                // the call's own cost included
+                traced.gas_used = call.gas.saturating_sub(s.gas);
+                traced.reverted = !succeeded;
+                self.current_call().calls.push(traced);
+            }
+        }
 
-            data.append(&mut y.to_vec());
+        // frames deeper than this step are over, the parent's stack holds how each of them went
+        while self.frames.len() as u64 > s.depth && self.frames.len() > 1 {
+            let mut frame = match self.frames.pop() {
+                Some(frame) => frame,
+                None => break,
+            };
+            if call_result.is_zero() {
+                self.logs.truncate(frame.logs_before);
+                self.transfers.truncate(frame.transfers_before);
+            } else if frame.creates {
+                self.addresses[frame.address] = u256_to_address(call_result);
+            }
+            frame.call.reverted = call_result.is_zero();
+            frame.call.gas_used = frame.gas_start.saturating_sub(frame.gas_left);
+            self.current_call().calls.push(frame.call);
         }
 
-        let data = Bytes::from(data);
+        let frame = self.frames.len() - 1;
+        self.frames[frame].gas_left = s.gas.saturating_sub(s.gas_cost);
+        if s.op == "RETURN" || s.op == "REVERT" {
+            self.frames[frame].call.return_step = Some(step);
+        }
 
-        // get 3 topics
-        let topics = vec![
-            H256::from(u64_array_to_u8_array(stack[stack_length - 3].0)),
-            H256::from(u64_array_to_u8_array(stack[stack_length - 4].0)),
-            H256::from(u64_array_to_u8_array(stack[stack_length - 5].0)),
-            H256::from(u64_array_to_u8_array(stack[stack_length - 6].0)), // Only used if opcode == log4
-        ];
+        let value_at = |position: usize| {
+            stack
+                .len()
+                .checked_sub(position)
+                .map(|i| stack[i])
+                .unwrap_or_default()
+        };
+        // the address the call runs as and the one whose code it runs
+        let target = match s.op.as_str() {
+            "CALL" | "STATICCALL" => {
+                let target = self.add_address(u256_to_address(value_at(2)));
+                Some((target, target))
+            }
+            "DELEGATECALL" | "CALLCODE" => Some((
+                self.current_address(),
+                self.add_address(u256_to_address(value_at(2))),
+            )),
+            "CREATE" | "CREATE2" => {
+                let target = self.add_address(Address::zero());
+                Some((target, target))
+            }
+            op if op.starts_with("LOG") => {
+                self.logs.push((self.current_address(), step));
+                let call = self.current_call();
+                call.logs.push((call.calls.len(), step));
+                None
+            }
+            _ => None,
+        };
+        if let Some((target, code)) = target {
+            let value = match s.op.as_str() {
+                "CALL" | "CALLCODE" => value_at(3),
+                "CREATE" | "CREATE2" => value_at(1),
+                _ => U256::zero(),
+            };
+            self.pending_call = Some(PendingCall {
+                step,
+                depth: s.depth,
+                gas: s.gas,
+                op: s.op.clone(),
+                target,
+                code,
+                value,
+            });
+        }
+    }
 
-        let address: Address = call_stack[(struct_log.depth - 1) as usize];
+    fn current_address(&self) -> usize {
+        self.frames[self.frames.len() - 1].address
+    }
 
-        logs.push(MyLog {
-            address,
-            topics,
-            data,
-        });
+    fn current_call(&mut self) -> &mut TracedCall {
+        let frame = self.frames.len() - 1;
+        &mut self.frames[frame].call
     }
 
-    let mut simulated_infos: Vec<SimulationResults> = Vec::new();
+    fn add_address(&mut self, address: Address) -> usize {
+        self.addresses.push(address);
+        self.addresses.len() - 1
+    }
 
-    for log in logs.into_iter() {
-        match process_logs(log, provider.clone()).await {
-            Ok(Some(x)) => simulated_infos.push(x),
-            Ok(None) => {}
-            Err(err) => {
-                eprintln!("Err {}", err);
-                process::exit(1)
+    // closes the frames the trace stopped in the middle of and reads the memory of the tree's calls and logs
+    fn call_tree(&mut self, struct_logs: &[StructLog]) -> Result<CallFrame> {
+        while self.frames.len() > 1 {
+            if let Some(frame) = self.frames.pop() {
+                self.current_call().calls.push(frame.call);
             }
         }
+        match self.frames.pop() {
+            Some(frame) => frame.call.call_frame(&self.addresses, struct_logs),
+            None => Err(SimulationError::UnsupportedTrace(
+                "struct logs without a frame".to_owned(),
+            )),
+        }
+    }
+
+    // the surviving logs with the address that emitted them and the index of their struct log, and native transfers
+    fn finish(self) -> (Vec<(Address, usize)>, Vec<NativeTransfer>) {
+        let addresses = self.addresses;
+        let logs = self
+            .logs
+            .into_iter()
+            .map(|(address, step)| (addresses[address], step))
+            .collect();
+        let transfers = self
+            .transfers
+            .into_iter()
+            .map(|(logs_before, from, to, amount)| NativeTransfer {
+                logs_before,
+                from: addresses[from],
+                to: addresses[to],
+                amount,
+            })
+            .collect();
+
+        (logs, transfers)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ethers::{
+        abi::{encode, Token},
+        types::DefaultFrame,
+    };
+
+    fn fixture(json: &str) -> DefaultFrame {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn addresses(logs: &[RawLog]) -> Vec<Address> {
+        logs.iter().map(|log| log.address).collect()
+    }
+
+    #[test]
+    fn attributes_delegatecalled_logs_to_the_proxy() {
+        let trace = fixture(include_str!("fixtures/delegatecall_proxy.json"));
+        let (user, proxy, token) = (
+            Address::repeat_byte(0x11),
+            Address::repeat_byte(0xaa),
+            Address::repeat_byte(0xcc),
+        );
+
+        let (logs, transfers, call_tree) =
+            trace_logs(&trace.struct_logs, user, proxy, U256::zero()).unwrap();
+
+        // the implementation runs as the proxy, the precompile call never opens a frame
+        assert_eq!(addresses(&logs), vec![proxy, token]);
+        assert_eq!(logs[1].topics[1], H256::from(proxy));
+        assert!(transfers.is_empty());
+
+        let implementation = &call_tree.calls[0];
+        assert_eq!(
+            (implementation.kind, implementation.to),
+            (CallKind::DelegateCall, Address::repeat_byte(0xbb))
+        );
+        assert_eq!(implementation.input.len(), 0x24);
+        assert_eq!(implementation.logs[0].address, proxy);
+        let subcalls: Vec<(CallKind, Address, Address)> = implementation
+            .calls
+            .iter()
+            .map(|call| (call.kind, call.from, call.to))
+            .collect();
+        assert_eq!(
+            subcalls,
+            vec![
+                (CallKind::Call, proxy, token),
+                (CallKind::StaticCall, proxy, Address::from_low_u64_be(1))
+            ]
+        );
+    }
+
+    #[test]
+    fn follows_created_contracts_reverts_and_selfdestructs() {
+        let trace = fixture(include_str!("fixtures/create_and_selfdestruct.json"));
+        let (user, factory, created, destroyed) = (
+            Address::repeat_byte(0x11),
+            Address::repeat_byte(0xff),
+            Address::repeat_byte(0xdd),
+            Address::repeat_byte(0xee),
+        );
+
+        let (logs, transfers, call_tree) =
+            trace_logs(&trace.struct_logs, user, factory, U256::from(9)).unwrap();
+
+        // the log of the reverted call and the value it carried are gone
+        assert_eq!(addresses(&logs), vec![created, destroyed]);
+        assert_eq!(logs[1].log_index, 1);
+        let transfers: Vec<(usize, Address, Address, U256)> = transfers
+            .iter()
+            .map(|t| (t.logs_before, t.from, t.to, t.amount))
+            .collect();
+        assert_eq!(
+            transfers,
+            vec![
+                (0, user, factory, U256::from(9)),
+                (1, factory, destroyed, U256::from(5))
+            ]
+        );
+
+        // the reverted call keeps its place in the tree
+        let calls: Vec<(CallKind, Address, bool)> = call_tree
+            .calls
+            .iter()
+            .map(|call| (call.kind, call.to, call.reverted))
+            .collect();
+        assert_eq!(
+            calls,
+            vec![
+                (CallKind::Create2, created, false),
+                (CallKind::Call, Address::repeat_byte(0x99), true),
+                (CallKind::Call, destroyed, false)
+            ]
+        );
+        assert_eq!(call_tree.calls[0].logs[0].address, created);
+        assert_eq!(call_tree.calls[0].output.len(), 0x40);
+        assert_eq!(call_tree.calls[0].gas_used, U256::from(13));
+    }
+
+    #[test]
+    fn rebuilds_logs_of_every_arity() {
+        let trace = fixture(include_str!("fixtures/log_arities.json"));
+        let (user, weth) = (Address::repeat_byte(0x11), Address::repeat_byte(0xcc));
+
+        let (logs, _, _) = trace_logs(&trace.struct_logs, user, weth, U256::zero()).unwrap();
+
+        let topic_counts: Vec<usize> = logs.iter().map(|log| log.topics.len()).collect();
+        assert_eq!(topic_counts, vec![0, 1, 2, 4, 0]);
+
+        assert_eq!(logs[0].data, "0xdeadbeefcafebabe".parse::<Bytes>().unwrap());
+        assert!(logs[1].data.is_empty());
+        assert_eq!(logs[2].topics[1], H256::from(user));
+        assert_eq!(
+            logs[2].data.to_vec(),
+            encode(&[Token::Uint(U256::from(42))])
+        );
+        assert_eq!(
+            logs[3].data.to_vec(),
+            encode(&[Token::Uint(U256::from(42)), Token::Uint(U256::from(1))])
+        );
+        assert_eq!(
+            logs[4].data.as_ref(),
+            [[0u8; 7].as_slice(), &[1], &[0u8; 8]].concat()
+        );
+    }
+
+    #[test]
+    fn only_falls_back_without_a_call_tracer() {
+        assert!(lacks_call_tracer(
+            -32601,
+            "the method debug_traceCall does not exist"
+        ));
+        assert!(lacks_call_tracer(-32000, "tracer not found"));
+        assert!(!lacks_call_tracer(-32000, "request timed out"));
+        assert!(!lacks_call_tracer(3, "execution reverted"));
     }
 
-    Ok(simulated_infos)
+    #[test]
+    fn flattens_call_tracer_output_in_execution_order() {
+        let frame: GethCallFrame =
+            serde_json::from_str(include_str!("fixtures/call_tracer_swap.json")).unwrap();
+        let call_tree = CallFrame::from(frame);
+        let (user, router, pair, weth) = (
+            Address::repeat_byte(0x11),
+            Address::repeat_byte(0x77),
+            Address::repeat_byte(0x88),
+            Address::repeat_byte(0xcc),
+        );
+
+        assert_eq!(call_tree.calls.len(), 5);
+        assert_eq!(call_tree.calls[2].kind, CallKind::DelegateCall);
+        assert!(call_tree.calls[4].reverted);
+
+        let outcome = call_tree_outcome(call_tree);
+        assert!(!outcome.failed);
+        // nothing of the reverted call survives
+        assert_eq!(addresses(&outcome.logs), vec![weth, pair, weth, router]);
+        assert_eq!(outcome.logs[3].log_index, 3);
+
+        let ether = U256::exp10(18);
+        let transfers: Vec<(usize, Address, Address, U256)> = outcome
+            .transfers
+            .iter()
+            .map(|t| (t.logs_before, t.from, t.to, t.amount))
+            .collect();
+        // weth pays the router before logging the withdrawal, which then pays the user
+        assert_eq!(
+            transfers,
+            vec![(2, weth, router, ether), (3, router, user, ether)]
+        );
+    }
 }
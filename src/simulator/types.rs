@@ -1,135 +1,732 @@
-use ethers::{
-    types::{Address, Bytes, H256, U256},
-    utils::parse_ether,
-};
-use eyre::Result;
-use std::process;
-
-#[derive(Debug)]
-pub struct MyLog {
+use ethers::types::{Address, Bytes, H256, I256, U256};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+
+use super::error::SimulationError;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RawLog {
+    // position of the log within the simulated transaction
+    pub log_index: usize,
     pub address: Address,
     pub topics: Vec<H256>,
     pub data: Bytes,
 }
 
 // Types
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Operation {
     Approval,
     Transfer,
     ApprovalForAll,
     TransferSingle,
     TransferBatch,
+    // a token transfer from the zero address
+    Mint,
+    // a token transfer to the zero address or a burn address
+    Burn,
+    // native currency wrapped into its erc20 (WETH `Deposit`)
+    Wrap,
+    // an erc20 wrapped native currency unwrapped (WETH `Withdrawal`)
+    Unwrap,
+    // assets deposited into an ERC4626 vault for shares
+    Deposit,
+    // ERC4626 vault shares redeemed for assets
+    Withdraw,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Standard {
     None,
     Eip20,
     Eip721,
     Eip1155,
+    // an ERC4626 vault, its token being the vault's shares
+    Eip4626,
+    // the chain's own currency moved by a call's value, the token address is the zero address
+    Native,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TokenInfo {
     pub standard: Standard,
     pub address: Address,
     pub name: String,
     pub symbol: String,
+    #[serde(with = "super::utils::u256_dec")]
     pub decimals: U256,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct SimulationResults {
     pub operation: Operation,
     pub token_info: TokenInfo,
     pub from: Address,
     pub to: Address,
+    #[serde(with = "super::utils::option_u256_dec")]
+    pub id: Option<U256>,
+    #[serde(with = "super::utils::u256_dec")]
+    pub amount: U256,
+    // log index of the TransferBatch the result was expanded from, shared by every id it transferred
+    #[serde(default)]
+    pub batch_log_index: Option<usize>,
+    // what a wrap or vault operation exchanged the token for, see `UnderlyingAmount`
+    #[serde(default)]
+    pub underlying: Option<UnderlyingAmount>,
+}
+
+// The other side of a wrap or vault operation: the native currency behind a wrapped token, or the asset held by a
+// vault, while the result's own token and amount are the wrapped token or the vault's shares
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct UnderlyingAmount {
+    pub token_info: TokenInfo,
+    #[serde(with = "super::utils::u256_dec")]
+    pub amount: U256,
+}
+
+// An event decoded by a custom `EventDecoder` or from the supplied abis
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CustomEvent {
+    pub log_index: usize,
+    pub address: Address,
+    pub name: String,
+    // in the event's order
+    pub fields: Vec<EventField>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventField {
+    pub name: String,
+    // the solidity type, e.g. `uint256`
+    pub kind: String,
+    pub value: String,
+}
+
+// A swap on a DEX pool, reported next to the transfers that moved its tokens
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Swap {
+    pub log_index: usize,
+    pub protocol: SwapProtocol,
+    // for Balancer the pool of the swap's pool id, the tokens being held by its vault
+    pub pool: Address,
+    // who called the pool, Balancer doesn't log it
+    pub sender: Option<Address>,
+    // who got the bought tokens, Balancer doesn't log it
+    pub recipient: Option<Address>,
+    pub token_in: TokenInfo,
+    #[serde(with = "super::utils::u256_dec")]
+    pub amount_in: U256,
+    pub token_out: TokenInfo,
+    #[serde(with = "super::utils::u256_dec")]
+    pub amount_out: U256,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SwapProtocol {
+    UniswapV2,
+    UniswapV3,
+    Curve,
+    Balancer,
+}
+
+// An order fulfilled on Seaport: who sold what to whom, for how much and with which fees
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sale {
+    pub log_index: usize,
+    // the Seaport contract
+    pub marketplace: Address,
+    pub order_hash: H256,
+    pub offerer: Address,
+    // gets the offer items
+    pub recipient: Address,
+    // The one giving the nfts away and the one getting them. The offerer of a listing is its seller, the offerer of
+    // a bid its buyer.
+    pub seller: Address,
+    pub buyer: Address,
+    pub offer: Vec<SaleItem>,
+    pub consideration: Vec<SaleItem>,
+    // consideration paid to anyone but the seller
+    pub fees: Vec<SaleFee>,
+    // only known when nfts were sold for a single currency
+    pub price: Option<SalePrice>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SaleItem {
+    pub token_info: TokenInfo,
+    #[serde(with = "super::utils::option_u256_dec")]
     pub id: Option<U256>,
+    #[serde(with = "super::utils::u256_dec")]
+    pub amount: U256,
+    // who gets a consideration item, offer items all go to the sale's recipient
+    pub recipient: Option<Address>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SaleFee {
+    pub kind: FeeKind,
+    pub recipient: Address,
+    pub token_info: TokenInfo,
+    #[serde(with = "super::utils::u256_dec")]
     pub amount: U256,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeKind {
+    // paid to the receiver the nft reports through ERC-2981
+    Royalty,
+    // any other fee, usually the marketplace's
+    Marketplace,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SalePrice {
+    pub token_info: TokenInfo,
+    // what the buyer paid, fees included
+    #[serde(with = "super::utils::u256_dec")]
+    pub total: U256,
+    // the total split over every nft sold, ERC1155 ones counted by their amount
+    #[serde(with = "super::utils::u256_dec")]
+    pub per_item: U256,
+}
+
+// What an address gains and loses in the transaction, by token
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AssetChanges {
+    pub address: Address,
+    // the transaction's sender, always listed first
+    pub sender: bool,
+    pub tokens: Vec<TokenChange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenChange {
+    pub token_info: TokenInfo,
+    // net amount received (positive) or sent (negative), the net number of items for nfts
+    #[serde(with = "super::utils::i256_dec")]
+    pub delta: I256,
+    // nft ids received or sent on balance
+    pub gained: Vec<NftAmount>,
+    pub lost: Vec<NftAmount>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NftAmount {
+    #[serde(with = "super::utils::u256_dec")]
+    pub id: U256,
+    // always 1 for ERC721
+    #[serde(with = "super::utils::u256_dec")]
+    pub amount: U256,
+}
+
+// A balance read on the fork right before and after the transaction, next to the change its events account for. A
+// mismatch points at a rebasing or fee-on-transfer token, or at events that don't tell the truth.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BalanceCheck {
+    pub address: Address,
+    pub token_info: TokenInfo,
+    // the nft id, an ERC721 balance being 1 when the address owns it and 0 otherwise
+    #[serde(with = "super::utils::option_u256_dec")]
+    pub id: Option<U256>,
+    #[serde(with = "super::utils::u256_dec")]
+    pub before: U256,
+    #[serde(with = "super::utils::u256_dec")]
+    pub after: U256,
+    #[serde(with = "super::utils::i256_dec")]
+    pub delta: I256,
+    // what `asset_changes` derived from the events
+    #[serde(with = "super::utils::i256_dec")]
+    pub expected_delta: I256,
+    pub mismatch: bool,
+}
+
+// Why a report has no balance checks
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkippedBalanceChecks {
+    // traced, run on the revm fork or refused by the node, no block holds the state before and after the transaction
+    NotMined,
+    // mined in one block along with other transactions, e.g. the ones replayed before a position, whose changes the
+    // balances before and after the block would include
+    SharedBlock,
+    // the fork couldn't serve the reads, e.g. a chain without the multicall contract
+    ReadsFailed,
+}
+
+// Everything we know about a simulated transaction. `results` only holds the watched token events, every log
+// (watched or not) is kept in `logs`.
+#[derive(Debug, PartialEq)]
+pub struct SimulationReport {
+    pub results: Vec<SimulationResults>,
+    pub success: bool,
+    pub gas_used: U256,
+    // only known when the transaction was actually mined on a fork
+    pub effective_gas_price: Option<U256>,
+    pub block_number: Option<u64>,
+    // hash of the transaction on the fork, trace and in-process simulations never produce one
+    pub tx_hash: Option<H256>,
+    pub logs: Vec<RawLog>,
+    pub revert_data: Option<Bytes>,
+    pub revert_reason: Option<RevertReason>,
+    // why the node or the evm refused to execute the transaction at all, e.g. a sender that can't cover its value
+    pub rejection: Option<String>,
+    // only traced simulations and the anvil fork's call tracer record the calls, and only when the node can report the
+    // whole tree
+    pub call_tree: Option<CallFrame>,
+    // events decoded by custom decoders, see `EventDecoder`
+    pub custom_events: Vec<CustomEvent>,
+    pub swaps: Vec<Swap>,
+    pub sales: Vec<Sale>,
+    // log_index of the logs no decoder handled, they are only reported raw in `logs`
+    pub undecoded_logs: Vec<usize>,
+    // net balance changes of every address involved, see `AssetChanges`
+    pub asset_changes: Vec<AssetChanges>,
+    // only transactions mined on a fork leave the states before and after them to read balances from
+    pub balance_checks: Option<Vec<BalanceCheck>>,
+    // why `balance_checks` is `None`
+    pub balance_checks_skipped: Option<SkippedBalanceChecks>,
+}
+
+impl SimulationReport {
+    // report for a transaction the node refused to execute because it reverts
+    pub fn reverted(revert_data: Bytes, block_number: Option<u64>) -> Self {
+        SimulationReport {
+            results: Vec::new(),
+            success: false,
+            gas_used: U256::zero(),
+            effective_gas_price: None,
+            block_number,
+            tx_hash: None,
+            logs: Vec::new(),
+            revert_data: Some(revert_data),
+            revert_reason: None,
+            rejection: None,
+            call_tree: None,
+            custom_events: Vec::new(),
+            swaps: Vec::new(),
+            sales: Vec::new(),
+            undecoded_logs: Vec::new(),
+            asset_changes: Vec::new(),
+            balance_checks: None,
+            balance_checks_skipped: None,
+        }
+    }
+
+    // report for a transaction that never ran, a batch records it and moves on to the next one
+    pub fn rejected(message: String, block_number: Option<u64>) -> Self {
+        SimulationReport {
+            revert_data: None,
+            rejection: Some(message),
+            ..SimulationReport::reverted(Bytes::new(), block_number)
+        }
+    }
+}
+
+// A call (or creation, or selfdestruct) made while executing the transaction, the top one being the transaction itself
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CallFrame {
+    pub kind: CallKind,
+    pub from: Address,
+    // the created contract for creations, the beneficiary for selfdestructs
+    pub to: Address,
+    #[serde(with = "super::utils::u256_dec")]
+    pub value: U256,
+    #[serde(with = "super::utils::u256_dec")]
+    pub gas_used: U256,
+    pub input: Bytes,
+    // the revert data when the call reverted
+    pub output: Bytes,
+    pub reverted: bool,
+    pub calls: Vec<CallFrame>,
+    // emitted by this frame itself, not by its subcalls
+    pub logs: Vec<FrameLog>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CallKind {
+    Call,
+    StaticCall,
+    DelegateCall,
+    CallCode,
+    Create,
+    Create2,
+    SelfDestruct,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FrameLog {
+    pub address: Address,
+    pub topics: Vec<H256>,
+    pub data: Bytes,
+    // number of the frame's child calls made before the log was emitted
+    pub position: usize,
+}
+
+// the opcode that opened the frame
+impl fmt::Display for CallKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let opcode = match self {
+            CallKind::Call => "CALL",
+            CallKind::StaticCall => "STATICCALL",
+            CallKind::DelegateCall => "DELEGATECALL",
+            CallKind::CallCode => "CALLCODE",
+            CallKind::Create => "CREATE",
+            CallKind::Create2 => "CREATE2",
+            CallKind::SelfDestruct => "SELFDESTRUCT",
+        };
+        f.write_str(opcode)
+    }
+}
+
+// Reports of a batch of transactions simulated one after the other on the same fork, in order
+#[derive(Debug, PartialEq)]
+pub struct BatchReport {
+    pub reports: Vec<SimulationReport>,
+    pub summary: BatchSummary,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BatchSummary {
+    pub transactions: usize,
+    pub succeeded: usize,
+    pub reverted: usize,
+    #[serde(with = "super::utils::u256_dec")]
+    pub total_gas_used: U256,
+    // watched events detected across the whole batch
+    pub total_results: usize,
+    pub mints: usize,
+    pub burns: usize,
+}
+
+impl BatchSummary {
+    pub fn from_reports(reports: &[SimulationReport]) -> Self {
+        let mut summary = BatchSummary::default();
+
+        for report in reports.iter() {
+            summary.transactions += 1;
+            match report.success {
+                true => summary.succeeded += 1,
+                false => summary.reverted += 1,
+            }
+            summary.total_gas_used += report.gas_used;
+            summary.total_results += report.results.len();
+            for result in report.results.iter() {
+                match result.operation {
+                    Operation::Mint => summary.mints += 1,
+                    Operation::Burn => summary.burns += 1,
+                    _ => {}
+                }
+            }
+        }
+
+        summary
+    }
+}
+
+// A transaction of a batch file, the fork it runs on is configured through the cli flags
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchTransaction {
+    pub from: Address,
+    pub to: Address,
+    #[serde(default)]
+    pub data: Bytes,
+    // in wei, see `u256_hex_or_dec` for how it can be written
+    #[serde(
+        default,
+        serialize_with = "super::utils::u256_dec::serialize",
+        deserialize_with = "super::utils::u256_hex_or_dec::deserialize"
+    )]
+    pub value: U256,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RevertReason {
+    // `Error(string)`, thrown by `require` and `revert("...")`
+    Error(String),
+    // `Panic(uint256)`, thrown by failed asserts, overflows, out of bounds access, ...
+    Panic {
+        code: U256,
+        description: String,
+    },
+    // a custom error matched against the supplied abis, args are (name, value) pairs
+    Custom {
+        name: String,
+        args: Vec<(String, String)>,
+    },
+    Unknown(Bytes),
+}
+
+impl fmt::Display for RevertReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RevertReason::Error(reason) => write!(f, "Error({:?})", reason),
+            RevertReason::Panic { code, description } => {
+                write!(f, "Panic(0x{:02x}): {}", code, description)
+            }
+            RevertReason::Custom { name, args } => {
+                let args: Vec<String> = args
+                    .iter()
+                    .map(|(name, value)| match name.is_empty() {
+                        true => value.clone(),
+                        false => format!("{}: {}", name, value),
+                    })
+                    .collect();
+                write!(f, "{}({})", name, args.join(", "))
+            }
+            RevertReason::Unknown(data) => write!(f, "unknown revert data {}", data),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum BlockNumberType {
     Past(u64),
+    #[default]
     Latest,
+    // as if inserted at `index` within `block`: forks at the parent block and replays the `index` transactions mined
+    // before that position first
+    Position {
+        block: u64,
+        index: u64,
+    },
 }
 
-#[derive(Debug)]
+impl BlockNumberType {
+    // the block the fork is created at, `None` for the latest one
+    pub fn fork_block(&self) -> Option<u64> {
+        match self {
+            BlockNumberType::Past(num) => Some(*num),
+            BlockNumberType::Latest => None,
+            BlockNumberType::Position { block, .. } => Some(block.saturating_sub(1)),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SimulationBackend {
+    // spawns (or connects to, when persisting) an anvil fork and runs the transaction on it
+    #[default]
+    Anvil,
+    // executes the transaction in-process on a fork database lazily fetched from the rpc
+    Revm,
+}
+
+// Which json-rpc namespace traces the transaction in trace mode
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TraceNamespace {
+    // `debug`, switching to `trace` when the node doesn't serve `debug_traceCall`
+    #[default]
+    Auto,
+    // geth's `debug_traceCall`
+    Debug,
+    // the parity style `trace_call` of Erigon, Nethermind and Reth, its logs rebuilt from the vm trace
+    Trace,
+}
+
+// Everything needed to run a simulation. Build it with `SimulationParams::builder()` or deserialize it from a file,
+// only `from` and `to` are required there.
+#[derive(Debug, Serialize, Deserialize)]
 pub struct SimulationParams {
     pub from: Address,
     pub to: Address,
+    #[serde(default)]
     pub data: Bytes,
+    // in wei, see `u256_hex_or_dec` for how it can be written
+    #[serde(
+        default,
+        deserialize_with = "super::utils::u256_hex_or_dec::deserialize"
+    )]
     pub value: U256,
+    // left to the node (or the block gas limit in-process) when not set
+    #[serde(default)]
+    pub gas_limit: Option<U256>,
+    #[serde(default)]
+    pub gas_price: Option<U256>,
+    #[serde(default)]
     pub block_number: BlockNumberType,
+    #[serde(default)]
     pub rpc_url: Option<String>,
+    #[serde(default)]
     pub persist: bool,
+    #[serde(default)]
+    pub backend: SimulationBackend,
+    #[serde(default)]
+    pub trace_namespace: TraceNamespace,
+    // abi files, or directories of them, used to decode custom errors and events
+    #[serde(default)]
+    pub abi_paths: Vec<PathBuf>,
+    // where tokens sent count as burned, on top of the zero address and 0x000000000000000000000000000000000000dEaD
+    #[serde(default)]
+    pub burn_addresses: Vec<Address>,
 }
 
 impl SimulationParams {
-    pub fn new(args: &Vec<String>) -> Result<Self, &str> {
-        let from = args[0].parse::<Address>();
-        let from = match from {
-            Ok(f) => f,
-            _ => return Err("invalid 'from' address provided"),
-        };
+    pub fn builder() -> SimulationParamsBuilder {
+        SimulationParamsBuilder::default()
+    }
+}
 
-        let to = args[1].parse::<Address>();
-        let to = match to {
-            Ok(t) => t,
-            _ => return Err("Invalid 'to' address provided"),
-        };
+#[derive(Debug, Default)]
+pub struct SimulationParamsBuilder {
+    from: Option<Address>,
+    to: Option<Address>,
+    data: Bytes,
+    value: U256,
+    gas_limit: Option<U256>,
+    gas_price: Option<U256>,
+    block_number: BlockNumberType,
+    rpc_url: Option<String>,
+    persist: bool,
+    backend: SimulationBackend,
+    trace_namespace: TraceNamespace,
+    abi_paths: Vec<PathBuf>,
+    burn_addresses: Vec<Address>,
+}
 
-        let data;
-        if args[3] == "" {
-            data = "0x".parse::<Bytes>();
-        } else {
-            data = args[2].parse::<Bytes>();
-        }
-        let data = match data {
-            Ok(d) => d,
-            _ => return Err("Invalid 'input data' provided"),
-        };
+impl SimulationParamsBuilder {
+    pub fn from(mut self, from: Address) -> Self {
+        self.from = Some(from);
+        self
+    }
 
-        let value = parse_ether(args[3].as_str());
-        let value = match value {
-            Ok(val) => val,
-            _ => return Err("Invalid ether value provided"),
-        };
+    pub fn to(mut self, to: Address) -> Self {
+        self.to = Some(to);
+        self
+    }
 
-        let block_number = if args[4].len() == 0 {
-            BlockNumberType::Latest
-        } else {
-            let block_number = args[4].parse::<u64>();
-            let block_number = match block_number {
-                Ok(num) => BlockNumberType::Past(num),
-                _ => return Err("Block number parsed in not a valid number. To use the current block number, parse in an empty string e.g '' or don't specify a block number at all"),
-            };
-            block_number
-        };
+    pub fn data(mut self, data: impl Into<Bytes>) -> Self {
+        self.data = data.into();
+        self
+    }
 
-        let rpc_url = if args[5].len() == 0 {
-            None
-        } else {
-            Some(args[5].to_owned())
-        };
+    pub fn value_wei(mut self, value: U256) -> Self {
+        self.value = value;
+        self
+    }
 
-        let persist = match args[6].len() {
-            0 => false,
-            _ => args[6].parse::<bool>().unwrap_or_else(|_| {
-                eprintln!("invalid boolean parameter for field 'persist'.");
-                process::exit(1)
-            }),
-        };
+    pub fn gas_limit(mut self, gas_limit: U256) -> Self {
+        self.gas_limit = Some(gas_limit);
+        self
+    }
+
+    // ignored by the revm backend, which like `eth_call` never charges for gas
+    pub fn gas_price(mut self, gas_price: U256) -> Self {
+        self.gas_price = Some(gas_price);
+        self
+    }
+
+    // fork at (and simulate on top of) this block instead of the latest one
+    pub fn block(mut self, block_number: u64) -> Self {
+        self.block_number = BlockNumberType::Past(block_number);
+        self
+    }
+
+    // simulate as if the transaction was mined at `index` within `block`, see `BlockNumberType::Position`
+    pub fn position(mut self, block: u64, index: u64) -> Self {
+        self.block_number = BlockNumberType::Position { block, index };
+        self
+    }
+
+    // defaults to the `RPC_URL` in the environment (or `.env` file) when not set
+    pub fn rpc_url(mut self, rpc_url: impl Into<String>) -> Self {
+        self.rpc_url = Some(rpc_url.into());
+        self
+    }
+
+    // run on the anvil instance at `rpc_url` as-is instead of spawning a fresh fork, so state carries over
+    pub fn persist(mut self, persist: bool) -> Self {
+        self.persist = persist;
+        self
+    }
+
+    pub fn backend(mut self, backend: SimulationBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    // only used when tracing, see `TraceNamespace`
+    pub fn trace_namespace(mut self, trace_namespace: TraceNamespace) -> Self {
+        self.trace_namespace = trace_namespace;
+        self
+    }
+
+    pub fn abi_path(mut self, abi_path: impl Into<PathBuf>) -> Self {
+        self.abi_paths.push(abi_path.into());
+        self
+    }
+
+    pub fn burn_address(mut self, burn_address: Address) -> Self {
+        self.burn_addresses.push(burn_address);
+        self
+    }
+
+    pub fn build(self) -> Result<SimulationParams, SimulationError> {
+        let from = self
+            .from
+            .ok_or_else(|| config_error("a 'from' address is required"))?;
+        let to = self
+            .to
+            .ok_or_else(|| config_error("a 'to' address is required"))?;
+
+        if let Some(rpc_url) = &self.rpc_url {
+            if rpc_url.is_empty() {
+                return Err(config_error("rpc url can not be empty"));
+            }
+        }
+        if self.persist && self.backend == SimulationBackend::Revm {
+            return Err(config_error(
+                "persisting state is only supported by the anvil backend",
+            ));
+        }
+        if self.persist && matches!(self.block_number, BlockNumberType::Position { .. }) {
+            return Err(config_error(
+                "simulating at a position within a block needs a fresh fork, it can't persist state",
+            ));
+        }
 
         Ok(SimulationParams {
             from,
             to,
-            data,
-            value,
-            block_number,
-            rpc_url,
-            persist,
+            data: self.data,
+            value: self.value,
+            gas_limit: self.gas_limit,
+            gas_price: self.gas_price,
+            block_number: self.block_number,
+            rpc_url: self.rpc_url,
+            persist: self.persist,
+            backend: self.backend,
+            trace_namespace: self.trace_namespace,
+            abi_paths: self.abi_paths,
+            burn_addresses: self.burn_addresses,
         })
     }
 }
+
+// How to re-run an already mined transaction with `simulator::replay`. The transaction itself (sender, target,
+// calldata, value and gas) is fetched from the rpc, this only describes the fork it runs on.
+#[derive(Debug, Default)]
+pub struct ReplayOptions {
+    // re-run on top of this block instead of at the transaction's original position
+    pub block_number: Option<u64>,
+    // first run the transactions mined before it in the same block, so it sees exactly the state it originally did
+    pub replay_preceding: bool,
+    pub rpc_url: Option<String>,
+    pub backend: SimulationBackend,
+    // only used when tracing, see `TraceNamespace`
+    pub trace_namespace: TraceNamespace,
+    pub abi_paths: Vec<PathBuf>,
+    pub burn_addresses: Vec<Address>,
+}
+
+fn config_error(msg: &str) -> SimulationError {
+    SimulationError::Config(msg.to_owned())
+}
@@ -1,4 +1,7 @@
-use ethers::types::{Address, H256, U256};
+use ethers::{
+    abi::Token,
+    types::{Address, Bytes, H256, I256, U256},
+};
 
 pub fn u64_array_to_u8_array(input: [u64; 4]) -> [u8; 32] {
     let mut output = [0; 32];
@@ -18,6 +21,134 @@ pub fn u256_to_address(input: U256) -> Address {
     Address::from(H256::from(u64_array_to_u8_array(input.0)))
 }
 
+// token amounts never get anywhere near the sign bit, anything that does saturates
+pub fn u256_to_i256(input: U256) -> I256 {
+    I256::try_from(input).unwrap_or(I256::MAX)
+}
+
+pub fn address_to_revm(input: Address) -> revm::primitives::Address {
+    revm::primitives::Address::from(input.0)
+}
+
+pub fn revm_to_address(input: revm::primitives::Address) -> Address {
+    Address::from(input.0 .0)
+}
+
+pub fn u256_to_revm(input: U256) -> revm::primitives::U256 {
+    revm::primitives::U256::from_limbs(input.0)
+}
+
+pub fn revm_to_u256(input: revm::primitives::U256) -> U256 {
+    U256(input.into_limbs())
+}
+
+// human readable rendering of a decoded abi value, numbers in decimal and bytes/addresses as 0x-prefixed hex
+pub fn format_token(token: &Token) -> String {
+    match token {
+        Token::Address(a) => format!("{:?}", a),
+        Token::Uint(u) => u.to_string(),
+        Token::Int(i) => I256::from_raw(*i).to_string(),
+        Token::Bool(b) => b.to_string(),
+        Token::String(s) => s.clone(),
+        Token::Bytes(b) | Token::FixedBytes(b) => Bytes::from(b.clone()).to_string(),
+        Token::Array(tokens) | Token::FixedArray(tokens) => format!(
+            "[{}]",
+            tokens
+                .iter()
+                .map(format_token)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Token::Tuple(tokens) => format!(
+            "({})",
+            tokens
+                .iter()
+                .map(format_token)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+// (de)serializes a U256 as a decimal string instead of ethers' default 0x-prefixed hex, for the json output
+pub mod u256_dec {
+    use ethers::types::U256;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &U256, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&value.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<U256, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        U256::from_dec_str(&value).map_err(D::Error::custom)
+    }
+}
+
+// reads a U256 written as a 0x-prefixed hex string, a decimal string or a json number, so input files can use
+// whichever
+pub mod u256_hex_or_dec {
+    use ethers::types::U256;
+    use serde::{de::Error, Deserialize, Deserializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Written {
+        Text(String),
+        Number(u64),
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<U256, D::Error> {
+        match Written::deserialize(deserializer)? {
+            Written::Number(value) => Ok(U256::from(value)),
+            Written::Text(value) => match value.strip_prefix("0x") {
+                Some(hex) => U256::from_str_radix(hex, 16).map_err(D::Error::custom),
+                None => U256::from_dec_str(&value).map_err(D::Error::custom),
+            },
+        }
+    }
+}
+
+pub mod option_u256_dec {
+    use ethers::types::U256;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<U256>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => serializer.serialize_some(&value.to_string()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<U256>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|value| U256::from_dec_str(&value).map_err(D::Error::custom))
+            .transpose()
+    }
+}
+
+// signed amounts, e.g. `-1500`
+pub mod i256_dec {
+    use ethers::types::I256;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &I256, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&value.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<I256, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        I256::from_dec_str(&value).map_err(D::Error::custom)
+    }
+}
+
+// debugging helper, kept around for dumping traces while developing
+#[allow(dead_code)]
 pub fn write_to_output_file<T: std::fmt::Debug>(to_write: &T) {
     // Specify the file path you want to write to
     let file_path: &str = "output.txt";
@@ -30,3 +161,35 @@ pub fn write_to_output_file<T: std::fmt::Debug>(to_write: &T) {
     // Write the data to the file
     std::io::Write::write_all(&mut file, st.as_bytes()).expect("failed to write to created file");
 }
+
+#[cfg(test)]
+mod test {
+    use crate::simulator::types::{BatchTransaction, SimulationParams};
+    use ethers::types::U256;
+
+    #[test]
+    fn reads_values_in_hex_or_decimal() {
+        let addresses = r#""from": "0x3B059f15059d976cA189165ee36d75Cb18249daf",
+            "to": "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2""#;
+        let one_ether = U256::exp10(18);
+
+        for value in [
+            r#""0xde0b6b3a7640000""#,
+            r#""1000000000000000000""#,
+            "1000000000000000000",
+        ] {
+            let json = format!("{{{}, \"value\": {}}}", addresses, value);
+            let tx: BatchTransaction = serde_json::from_str(&json).unwrap();
+            let params: SimulationParams = serde_json::from_str(&json).unwrap();
+            assert_eq!((tx.value, params.value), (one_ether, one_ether));
+        }
+
+        let json = format!("{{{}}}", addresses);
+        let tx: BatchTransaction = serde_json::from_str(&json).unwrap();
+        assert_eq!(tx.value, U256::zero());
+        assert!(serde_json::from_str::<BatchTransaction>(
+            &json.replace('}', r#", "value": "1 eth"}"#)
+        )
+        .is_err());
+    }
+}
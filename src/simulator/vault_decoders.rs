@@ -0,0 +1,281 @@
+use async_trait::async_trait;
+use ethers::{
+    prelude::abigen,
+    providers::{Http, Provider},
+    types::{Address, BlockNumber, H256, U256},
+};
+use std::sync::Arc;
+
+use super::constants::{
+    VAULT_DEPOSIT, VAULT_WITHDRAW, WETH_DEPOSIT, WETH_WITHDRAWAL, WRAPPED_NATIVE,
+};
+use super::decoder::{DecodeContext, DecodedEvent, EventDecoder};
+use super::error::Result;
+use super::process_logs::{get_token_name_and_symbol, native_token};
+use super::types::{Operation, RawLog, SimulationResults, Standard, TokenInfo, UnderlyingAmount};
+
+// WETH style Deposit and Withdrawal of a wrapped native currency, reported as wraps and unwraps with the native
+// currency as their underlying
+pub struct WethDecoder;
+
+// ERC4626 Deposit and Withdraw, with the vault's shares as the amount and the vault's asset as the underlying
+pub struct Erc4626Decoder;
+
+#[async_trait]
+impl EventDecoder for WethDecoder {
+    fn topics(&self) -> Vec<H256> {
+        vec![H256::from(WETH_DEPOSIT), H256::from(WETH_WITHDRAWAL)]
+    }
+
+    async fn decode(
+        &self,
+        log: &RawLog,
+        context: &mut DecodeContext<'_>,
+    ) -> Result<Vec<DecodedEvent>> {
+        let (operation, account, amount) = match wrap_movement(log) {
+            Some(movement) => movement,
+            None => return Ok(Vec::new()),
+        };
+        // staking contracts and other wrappers emit the very same Deposit, often along with a Transfer from the zero
+        // address that already moves the balance
+        if !is_wrapped_native(context.chain_id().await?, log.address) {
+            return Ok(Vec::new());
+        }
+
+        let (name, symbol, decimals) = get_token_name_and_symbol(
+            log.address,
+            context.provider.clone(),
+            context.block,
+            &Standard::Eip20,
+        )
+        .await?;
+
+        Ok(vec![DecodedEvent::Token(SimulationResults {
+            operation,
+            token_info: TokenInfo {
+                standard: Standard::Eip20,
+                address: log.address,
+                name,
+                symbol,
+                decimals,
+            },
+            from: account,
+            to: account,
+            id: None,
+            amount,
+            batch_log_index: None,
+            underlying: Some(UnderlyingAmount {
+                token_info: native_token(),
+                amount,
+            }),
+        })])
+    }
+}
+
+#[async_trait]
+impl EventDecoder for Erc4626Decoder {
+    fn topics(&self) -> Vec<H256> {
+        vec![H256::from(VAULT_DEPOSIT), H256::from(VAULT_WITHDRAW)]
+    }
+
+    async fn decode(
+        &self,
+        log: &RawLog,
+        context: &mut DecodeContext<'_>,
+    ) -> Result<Vec<DecodedEvent>> {
+        let movement = match vault_movement(log) {
+            Some(movement) => movement,
+            None => return Ok(Vec::new()),
+        };
+        let asset = match vault_asset(log.address, context.provider, context.block).await {
+            Some(asset) => asset,
+            None => return Ok(Vec::new()),
+        };
+
+        let (name, symbol, decimals) = get_token_name_and_symbol(
+            log.address,
+            context.provider.clone(),
+            context.block,
+            &Standard::Eip4626,
+        )
+        .await?;
+        let (asset_name, asset_symbol, asset_decimals) = get_token_name_and_symbol(
+            asset,
+            context.provider.clone(),
+            context.block,
+            &Standard::Eip20,
+        )
+        .await?;
+
+        Ok(vec![DecodedEvent::Token(SimulationResults {
+            operation: movement.operation,
+            token_info: TokenInfo {
+                standard: Standard::Eip4626,
+                address: log.address,
+                name,
+                symbol,
+                decimals,
+            },
+            from: movement.from,
+            to: movement.to,
+            id: None,
+            amount: movement.shares,
+            batch_log_index: None,
+            underlying: Some(UnderlyingAmount {
+                token_info: TokenInfo {
+                    standard: Standard::Eip20,
+                    address: asset,
+                    name: asset_name,
+                    symbol: asset_symbol,
+                    decimals: asset_decimals,
+                },
+                amount: movement.assets,
+            }),
+        })])
+    }
+}
+
+fn is_wrapped_native(chain_id: u64, address: Address) -> bool {
+    WRAPPED_NATIVE
+        .iter()
+        .any(|(chain, token)| *chain == chain_id && Address::from(*token) == address)
+}
+
+// The operation, account and amount of `Deposit(address indexed dst, uint256 wad)` or
+// `Withdrawal(address indexed src, uint256 wad)`, `None` when the log isn't shaped like them
+fn wrap_movement(log: &RawLog) -> Option<(Operation, Address, U256)> {
+    if log.topics.len() != 2 || log.data.len() != 32 {
+        return None;
+    }
+    let operation = match log.topics[0].to_fixed_bytes() {
+        WETH_DEPOSIT => Operation::Wrap,
+        WETH_WITHDRAWAL => Operation::Unwrap,
+        _ => return None,
+    };
+
+    Some((
+        operation,
+        Address::from(log.topics[1]),
+        U256::from_big_endian(&log.data),
+    ))
+}
+
+#[derive(Debug, PartialEq)]
+struct VaultMovement {
+    operation: Operation,
+    // pays the assets of a deposit, or has the shares of a withdrawal redeemed
+    from: Address,
+    // gets the shares of a deposit, or the assets of a withdrawal
+    to: Address,
+    assets: U256,
+    shares: U256,
+}
+
+// `Deposit(address indexed sender, address indexed owner, uint256 assets, uint256 shares)` or
+// `Withdraw(address indexed sender, address indexed receiver, address indexed owner, uint256 assets, uint256 shares)`,
+// `None` when the log isn't shaped like them
+fn vault_movement(log: &RawLog) -> Option<VaultMovement> {
+    if log.data.len() != 64 {
+        return None;
+    }
+    let (operation, from, to) = match (log.topics[0].to_fixed_bytes(), log.topics.len()) {
+        (VAULT_DEPOSIT, 3) => (Operation::Deposit, log.topics[1], log.topics[2]),
+        (VAULT_WITHDRAW, 4) => (Operation::Withdraw, log.topics[3], log.topics[2]),
+        _ => return None,
+    };
+
+    Some(VaultMovement {
+        operation,
+        from: Address::from(from),
+        to: Address::from(to),
+        assets: U256::from_big_endian(&log.data[..32]),
+        shares: U256::from_big_endian(&log.data[32..]),
+    })
+}
+
+// the vault's underlying token, `None` for contracts without an `asset()`, which aren't ERC4626 vaults
+async fn vault_asset(
+    vault: Address,
+    provider: &Provider<Http>,
+    block: BlockNumber,
+) -> Option<Address> {
+    abigen!(
+        VaultInstance,
+        r#"[
+            function asset() external view returns (address)
+        ]"#,
+    );
+
+    VaultInstance::new(vault, Arc::new(provider.clone()))
+        .asset()
+        .block(block)
+        .call()
+        .await
+        .ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::simulator::test_utils::{log, uints};
+
+    #[test]
+    fn reads_who_pays_and_who_receives() {
+        let (sender, owner, receiver) = (
+            Address::repeat_byte(0x11),
+            Address::repeat_byte(0x22),
+            Address::repeat_byte(0x33),
+        );
+
+        assert_eq!(
+            wrap_movement(&log(WETH_DEPOSIT, &[owner], uints(&[5]))),
+            Some((Operation::Wrap, owner, U256::from(5)))
+        );
+        assert_eq!(
+            wrap_movement(&log(WETH_WITHDRAWAL, &[owner], uints(&[5]))),
+            Some((Operation::Unwrap, owner, U256::from(5)))
+        );
+        let weth = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2"
+            .parse()
+            .unwrap();
+        assert!(is_wrapped_native(1, weth));
+        // not on another chain, nor any other token
+        assert!(!is_wrapped_native(137, weth));
+        assert!(!is_wrapped_native(1, owner));
+
+        // a Deposit with its amount indexed isn't WETH's
+        assert_eq!(
+            wrap_movement(&log(WETH_DEPOSIT, &[owner, owner], Vec::new())),
+            None
+        );
+
+        assert_eq!(
+            vault_movement(&log(VAULT_DEPOSIT, &[sender, owner], uints(&[100, 90]))),
+            Some(VaultMovement {
+                operation: Operation::Deposit,
+                from: sender,
+                to: owner,
+                assets: U256::from(100),
+                shares: U256::from(90),
+            })
+        );
+        assert_eq!(
+            vault_movement(&log(
+                VAULT_WITHDRAW,
+                &[sender, receiver, owner],
+                uints(&[100, 90])
+            )),
+            Some(VaultMovement {
+                operation: Operation::Withdraw,
+                from: owner,
+                to: receiver,
+                assets: U256::from(100),
+                shares: U256::from(90),
+            })
+        );
+        assert_eq!(
+            vault_movement(&log(VAULT_WITHDRAW, &[sender, receiver], uints(&[100, 90]))),
+            None
+        );
+    }
+}